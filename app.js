@@ -4,7 +4,18 @@ const API_BASE_URL = 'http://localhost:8080/api';
 // State Management
 let currentUser = null;
 let authToken = null;
+let refreshToken = null;
 let selectedTemplate = null;
+// Cursors for the next page of each meme list
+let memesCursor = null;
+let myMemesCursor = null;
+let profileCursor = null;
+let profileUsername = null;
+// Pending as-you-type search, so only the latest keystroke hits the API
+let searchTimer = null;
+let searchController = null;
+// Open live update stream, aborted when the user signs in or out
+let eventsController = null;
 
 // Initialize app
 document.addEventListener('DOMContentLoaded', () => {
@@ -12,6 +23,44 @@ document.addEventListener('DOMContentLoaded', () => {
     loadMemes();
 });
 
+// Validation messages keyed by the server's error code, so they can be
+// translated here; anything missing falls back to the server's message
+const VALIDATION_MESSAGES = {
+    length: (p) => p.min !== undefined && p.max !== undefined
+        ? `Must be between ${p.min} and ${p.max} characters`
+        : p.min !== undefined ? `Must be at least ${p.min} characters` : `Must be at most ${p.max} characters`,
+    email: () => 'Please enter a valid email address',
+    invalid_tags: () => 'Up to 10 tags of letters, digits or underscores',
+};
+
+// Show field-level errors from an API response next to the matching inputs.
+// `fieldInputs` maps API field names to input element IDs.
+function showFieldErrors(form, errors, fieldInputs) {
+    clearFieldErrors(form);
+    
+    let shown = false;
+    for (const [field, failures] of Object.entries(errors || {})) {
+        const input = document.getElementById(fieldInputs[field]);
+        if (!input || failures.length === 0) continue;
+        
+        const failure = failures[0];
+        const localize = VALIDATION_MESSAGES[failure.code];
+        const hint = document.createElement('small');
+        hint.className = 'field-error';
+        hint.textContent = localize ? localize(failure.params) : failure.message;
+        
+        input.classList.add('invalid');
+        input.insertAdjacentElement('afterend', hint);
+        shown = true;
+    }
+    return shown;
+}
+
+function clearFieldErrors(form) {
+    form.querySelectorAll('.field-error').forEach(hint => hint.remove());
+    form.querySelectorAll('.invalid').forEach(input => input.classList.remove('invalid'));
+}
+
 // Page Navigation
 function showPage(pageName) {
     document.querySelectorAll('.page').forEach(page => {
@@ -26,6 +75,8 @@ function showPage(pageName) {
     // Load data for specific pages
     if (pageName === 'myMemes' && currentUser) {
         loadUserMemes();
+    } else if (pageName === 'notifications' && currentUser) {
+        loadNotifications();
     } else if (pageName === 'home') {
         loadMemes();
     }
@@ -34,11 +85,14 @@ function showPage(pageName) {
 // Authentication
 function checkAuth() {
     authToken = localStorage.getItem('authToken');
+    refreshToken = localStorage.getItem('refreshToken');
     const userData = localStorage.getItem('userData');
     
     if (authToken && userData) {
         currentUser = JSON.parse(userData);
         updateUIForAuthenticatedUser();
+    } else {
+        connectEvents();
     }
 }
 
@@ -46,13 +100,18 @@ function updateUIForAuthenticatedUser() {
     document.getElementById('authButtons').style.display = 'none';
     document.getElementById('userMenu').style.display = 'flex';
     document.getElementById('myMemesLink').style.display = 'block';
+    document.getElementById('notificationsLink').style.display = 'block';
     document.getElementById('username').textContent = currentUser.username;
+    refreshUnreadCount();
+    connectEvents();
 }
 
 function updateUIForUnauthenticatedUser() {
     document.getElementById('authButtons').style.display = 'flex';
     document.getElementById('userMenu').style.display = 'none';
     document.getElementById('myMemesLink').style.display = 'none';
+    document.getElementById('notificationsLink').style.display = 'none';
+    connectEvents();
 }
 
 async function handleSignup(event) {
@@ -74,16 +133,18 @@ async function handleSignup(event) {
         const result = await response.json();
         
         if (result.success && result.data) {
-            authToken = result.data.token;
             currentUser = result.data.user;
-            
-            localStorage.setItem('authToken', authToken);
+            storeTokens(result.data);
             localStorage.setItem('userData', JSON.stringify(currentUser));
             
             updateUIForAuthenticatedUser();
             showPage('home');
             alert('Welcome to MemEmage! 🎉');
-        } else {
+        } else if (!showFieldErrors(event.target, result.errors, {
+            username: 'signupUsername',
+            email: 'signupEmail',
+            password: 'signupPassword',
+        })) {
             alert(result.error || 'Signup failed');
         }
     } catch (error) {
@@ -110,16 +171,17 @@ async function handleLogin(event) {
         const result = await response.json();
         
         if (result.success && result.data) {
-            authToken = result.data.token;
             currentUser = result.data.user;
-            
-            localStorage.setItem('authToken', authToken);
+            storeTokens(result.data);
             localStorage.setItem('userData', JSON.stringify(currentUser));
             
             updateUIForAuthenticatedUser();
             showPage('home');
             alert('Welcome back! 👋');
-        } else {
+        } else if (!showFieldErrors(event.target, result.errors, {
+            username: 'loginUsername',
+            password: 'loginPassword',
+        })) {
             alert(result.error || 'Login failed');
         }
     } catch (error) {
@@ -128,12 +190,73 @@ async function handleLogin(event) {
     }
 }
 
-function logout() {
+function storeTokens(tokens) {
+    authToken = tokens.token;
+    refreshToken = tokens.refresh_token;
+    localStorage.setItem('authToken', authToken);
+    localStorage.setItem('refreshToken', refreshToken);
+}
+
+function clearSession() {
     localStorage.removeItem('authToken');
+    localStorage.removeItem('refreshToken');
     localStorage.removeItem('userData');
     authToken = null;
+    refreshToken = null;
     currentUser = null;
     updateUIForUnauthenticatedUser();
+}
+
+// Trade the refresh token for a new pair; only one refresh runs at a time
+let refreshInFlight = null;
+function refreshSession() {
+    if (!refreshInFlight) {
+        refreshInFlight = fetch(`${API_BASE_URL}/auth/refresh`, {
+            method: 'POST',
+            headers: {
+                'Content-Type': 'application/json',
+            },
+            body: JSON.stringify({ refresh_token: refreshToken }),
+        })
+            .then(response => response.json())
+            .then(result => {
+                if (result.success && result.data) {
+                    storeTokens(result.data);
+                    return true;
+                }
+                clearSession();
+                return false;
+            })
+            .finally(() => { refreshInFlight = null; });
+    }
+    return refreshInFlight;
+}
+
+// fetch() with the access token, refreshing it once if it has expired
+async function authFetch(url, options = {}) {
+    const send = () => fetch(url, {
+        ...options,
+        headers: { ...options.headers, 'Authorization': `Bearer ${authToken}` },
+    });
+    
+    let response = await send();
+    if (response.status === 401 && refreshToken && await refreshSession()) {
+        response = await send();
+    }
+    return response;
+}
+
+async function logout(everywhere = false) {
+    if (authToken) {
+        try {
+            await authFetch(`${API_BASE_URL}/auth/${everywhere ? 'logout-all' : 'logout'}`, {
+                method: 'POST',
+            });
+        } catch (error) {
+            console.error('Logout error:', error);
+        }
+    }
+    clearSession();
     showPage('home');
 }
 
@@ -168,19 +291,22 @@ async function createMeme(event) {
     const title = document.getElementById('memeTitle').value;
     const topText = document.getElementById('topText').value;
     const bottomText = document.getElementById('bottomText').value;
+    const tags = document.getElementById('memeTags').value
+        .split(/[\s,]+/)
+        .filter(tag => tag.length > 0);
     
     try {
-        const response = await fetch(`${API_BASE_URL}/memes`, {
+        const response = await authFetch(`${API_BASE_URL}/memes`, {
             method: 'POST',
             headers: {
                 'Content-Type': 'application/json',
-                'Authorization': `Bearer ${authToken}`,
             },
             body: JSON.stringify({
                 title,
                 top_text: topText || null,
                 bottom_text: bottomText || null,
                 template_name: selectedTemplate,
+                tags,
             }),
         });
         
@@ -188,10 +314,16 @@ async function createMeme(event) {
         
         if (result.success) {
             alert('Meme created successfully! 🎉');
+            clearFieldErrors(event.target);
             document.getElementById('memeForm').reset();
             updatePreview();
             showPage('myMemes');
-        } else {
+        } else if (!showFieldErrors(event.target, result.errors, {
+            title: 'memeTitle',
+            top_text: 'topText',
+            bottom_text: 'bottomText',
+            tags: 'memeTags',
+        })) {
             alert(result.error || 'Failed to create meme');
         }
     } catch (error) {
@@ -200,72 +332,386 @@ async function createMeme(event) {
     }
 }
 
-async function loadMemes() {
+async function loadMemes(more = false) {
     const container = document.getElementById('memesContainer');
-    container.innerHTML = '<div class="loading">Loading memes...</div>';
+    if (!more) {
+        memesCursor = null;
+        container.innerHTML = '<div class="loading">Loading memes...</div>';
+    }
     
     try {
-        const response = await fetch(`${API_BASE_URL}/memes?limit=20`);
+        // Options are "following", "sort" or "sort:window"
+        const feed = document.getElementById('feedSort').value;
+        if (feed === 'following' && !authToken) {
+            showPage('login');
+            return;
+        }
+        if (feed === 'following') {
+            document.getElementById('newMemesNotice').style.display = 'none';
+        }
+        const [sort, range = 'all'] = feed.split(':');
+        let url = feed === 'following'
+            ? `${API_BASE_URL}/feed/following?limit=20`
+            : `${API_BASE_URL}/memes?limit=20&sort=${sort}&window=${range}`;
+        if (more && memesCursor) {
+            url += `&cursor=${encodeURIComponent(memesCursor)}`;
+        }
+        const response = authToken ? await authFetch(url) : await fetch(url);
         const result = await response.json();
         
         if (result.success && result.data) {
-            displayMemes(result.data, container);
+            displayMemes(result.data, container, more);
+            memesCursor = result.next_cursor || null;
         } else {
             container.innerHTML = '<div class="loading">No memes found</div>';
+            memesCursor = null;
         }
     } catch (error) {
         console.error('Load memes error:', error);
         container.innerHTML = '<div class="loading">Error loading memes</div>';
+        memesCursor = null;
     }
+    document.getElementById('loadMoreMemes').style.display = memesCursor ? 'block' : 'none';
 }
 
-async function loadUserMemes() {
+async function loadUserMemes(more = false) {
     if (!authToken) {
         showPage('login');
         return;
     }
     
     const container = document.getElementById('myMemesContainer');
-    container.innerHTML = '<div class="loading">Loading your memes...</div>';
+    if (!more) {
+        myMemesCursor = null;
+        container.innerHTML = '<div class="loading">Loading your memes...</div>';
+    }
     
     try {
-        const response = await fetch(`${API_BASE_URL}/memes/user/my-memes`, {
-            headers: {
-                'Authorization': `Bearer ${authToken}`,
-            },
-        });
+        let url = `${API_BASE_URL}/memes/user/my-memes?limit=20`;
+        if (more && myMemesCursor) {
+            url += `&cursor=${encodeURIComponent(myMemesCursor)}`;
+        }
+        const response = await authFetch(url);
         
         const result = await response.json();
         
         if (result.success && result.data) {
-            displayMemes(result.data, container);
+            displayMemes(result.data, container, more);
+            myMemesCursor = result.next_cursor || null;
         } else {
             container.innerHTML = '<div class="loading">You haven\'t created any memes yet</div>';
+            myMemesCursor = null;
         }
     } catch (error) {
         console.error('Load user memes error:', error);
         container.innerHTML = '<div class="loading">Error loading memes</div>';
+        myMemesCursor = null;
+    }
+    document.getElementById('loadMoreMyMemes').style.display = myMemesCursor ? 'block' : 'none';
+}
+
+function onSearchInput() {
+    clearTimeout(searchTimer);
+    searchTimer = setTimeout(searchMemes, 250);
+}
+
+async function searchMemes() {
+    const query = document.getElementById('searchInput').value.trim();
+    const sortSelect = document.getElementById('feedSort');
+    if (searchController) {
+        searchController.abort();
+    }
+    
+    if (!query) {
+        sortSelect.disabled = false;
+        loadMemes();
+        return;
+    }
+    
+    sortSelect.disabled = true;
+    document.getElementById('loadMoreMemes').style.display = 'none';
+    const container = document.getElementById('memesContainer');
+    searchController = new AbortController();
+    
+    try {
+        const response = await fetch(`${API_BASE_URL}/search?q=${encodeURIComponent(query)}&limit=20`, {
+            signal: searchController.signal,
+        });
+        const result = await response.json();
+        
+        if (result.success && result.data) {
+            // Highlights come back HTML-escaped, with matches in <mark>
+            displayMemes(result.data, container);
+        } else {
+            container.innerHTML = `<div class="loading">${escapeHtml(result.error || 'No memes found')}</div>`;
+        }
+    } catch (error) {
+        if (error.name !== 'AbortError') {
+            console.error('Search error:', error);
+            container.innerHTML = '<div class="loading">Error searching memes</div>';
+        }
+    }
+}
+
+async function showProfile(username) {
+    profileUsername = username;
+    showPage('profile');
+    
+    const header = document.getElementById('profileHeader');
+    header.innerHTML = '<div class="loading">Loading profile...</div>';
+    
+    try {
+        const response = await fetch(`${API_BASE_URL}/users/${encodeURIComponent(username)}`);
+        const result = await response.json();
+        
+        if (result.success && result.data) {
+            const profile = result.data;
+            header.innerHTML = `
+                <h2>${escapeHtml(profile.display_name || profile.username)}</h2>
+                <div class="meme-author">@${escapeHtml(profile.username)} · joined ${new Date(profile.joined_at).toLocaleDateString()}</div>
+                ${profile.bio ? `<p>${escapeHtml(profile.bio)}</p>` : ''}
+                <div class="profile-stats">
+                    <span>${profile.meme_count} memes</span>
+                    <span id="profileFollowers">${profile.follower_count} followers</span>
+                    <span>${profile.following_count} following</span>
+                    <span>❤️ ${profile.total_likes}</span>
+                    <span>👁️ ${profile.total_views}</span>
+                </div>
+                ${currentUser && currentUser.username !== profile.username
+                    ? `<button class="btn-secondary" onclick="toggleFollow(this)">${profile.followed_by_me ? 'Unfollow' : 'Follow'}</button>`
+                    : ''}
+            `;
+        } else {
+            header.innerHTML = `<div class="loading">${escapeHtml(result.error || 'User not found')}</div>`;
+        }
+    } catch (error) {
+        console.error('Load profile error:', error);
+        header.innerHTML = '<div class="loading">Error loading profile</div>';
+    }
+    
+    loadProfileMemes();
+}
+
+async function toggleFollow(button) {
+    const following = button.textContent === 'Unfollow';
+    try {
+        const response = await authFetch(`${API_BASE_URL}/users/${encodeURIComponent(profileUsername)}/follow`, {
+            method: following ? 'DELETE' : 'POST',
+        });
+        const result = await response.json();
+        
+        if (result.success && result.data) {
+            button.textContent = result.data.following ? 'Unfollow' : 'Follow';
+            document.getElementById('profileFollowers').textContent = `${result.data.followers} followers`;
+        }
+    } catch (error) {
+        console.error('Follow error:', error);
+    }
+}
+
+async function loadProfileMemes(more = false) {
+    const container = document.getElementById('profileMemesContainer');
+    if (!more) {
+        profileCursor = null;
+        container.innerHTML = '<div class="loading">Loading memes...</div>';
+    }
+    
+    try {
+        let url = `${API_BASE_URL}/users/${encodeURIComponent(profileUsername)}/memes?limit=20`;
+        if (more && profileCursor) {
+            url += `&cursor=${encodeURIComponent(profileCursor)}`;
+        }
+        const response = authToken ? await authFetch(url) : await fetch(url);
+        const result = await response.json();
+        
+        if (result.success && result.data) {
+            displayMemes(result.data, container, more);
+            profileCursor = result.next_cursor || null;
+        } else {
+            container.innerHTML = '<div class="loading">No memes found</div>';
+            profileCursor = null;
+        }
+    } catch (error) {
+        console.error('Load profile memes error:', error);
+        container.innerHTML = '<div class="loading">Error loading memes</div>';
+        profileCursor = null;
+    }
+    document.getElementById('loadMoreProfileMemes').style.display = profileCursor ? 'block' : 'none';
+}
+
+function setUnreadBadge(count) {
+    const badge = document.getElementById('unreadBadge');
+    badge.textContent = count;
+    badge.style.display = count > 0 ? 'inline-block' : 'none';
+}
+
+async function refreshUnreadCount() {
+    try {
+        const response = await authFetch(`${API_BASE_URL}/notifications?unread=true&limit=1`);
+        const result = await response.json();
+        if (result.success && result.data) {
+            setUnreadBadge(result.data.unread_count);
+        }
+    } catch (error) {
+        console.error('Unread count error:', error);
+    }
+}
+
+// Show the latest notifications, then mark them all read
+async function loadNotifications() {
+    const container = document.getElementById('notificationsContainer');
+    container.innerHTML = '<div class="loading">Loading notifications...</div>';
+    
+    try {
+        const response = await authFetch(`${API_BASE_URL}/notifications?limit=50`);
+        const result = await response.json();
+        
+        if (!result.success || !result.data) {
+            container.innerHTML = `<div class="loading">${escapeHtml(result.error || 'Error loading notifications')}</div>`;
+            return;
+        }
+        
+        const { notifications } = result.data;
+        container.innerHTML = notifications.length === 0
+            ? '<div class="loading">No notifications yet</div>'
+            : notifications.map(n => `
+                <div class="notification ${n.read ? '' : 'unread'}">
+                    <span>${escapeHtml(n.message)}</span>
+                    <time>${new Date(n.updated_at).toLocaleString()}</time>
+                </div>
+            `).join('');
+        
+        if (result.data.unread_count > 0) {
+            const readResponse = await authFetch(`${API_BASE_URL}/notifications/read`, {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({}),
+            });
+            const readResult = await readResponse.json();
+            if (readResult.success && readResult.data) {
+                setUnreadBadge(readResult.data.unread_count);
+            }
+        }
+    } catch (error) {
+        console.error('Load notifications error:', error);
+        container.innerHTML = '<div class="loading">Error loading notifications</div>';
     }
 }
 
-function displayMemes(memes, container) {
+// Live updates from GET /api/events. EventSource can't send the access
+// token, so the stream is read through fetch and parsed here
+async function connectEvents() {
+    if (eventsController) {
+        eventsController.abort();
+    }
+    const controller = new AbortController();
+    eventsController = controller;
+    
+    try {
+        const url = `${API_BASE_URL}/events`;
+        const options = { signal: controller.signal };
+        const response = authToken ? await authFetch(url, options) : await fetch(url, options);
+        if (!response.ok) {
+            throw new Error(`HTTP ${response.status}`);
+        }
+        
+        const reader = response.body.pipeThrough(new TextDecoderStream()).getReader();
+        let buffer = '';
+        for (;;) {
+            const { value, done } = await reader.read();
+            if (done) break;
+            
+            buffer += value;
+            let end;
+            while ((end = buffer.indexOf('\n\n')) !== -1) {
+                handleEventFrame(buffer.slice(0, end));
+                buffer = buffer.slice(end + 2);
+            }
+        }
+    } catch (error) {
+        if (controller.signal.aborted) return;
+        console.error('Live updates error:', error);
+    }
+    
+    // Reconnect unless a newer stream replaced this one
+    if (eventsController === controller) {
+        setTimeout(() => {
+            if (eventsController === controller) connectEvents();
+        }, 5000);
+    }
+}
+
+function handleEventFrame(frame) {
+    let name = 'message';
+    let data = '';
+    for (const line of frame.split('\n')) {
+        if (line.startsWith('event: ')) {
+            name = line.slice(7);
+        } else if (line.startsWith('data: ')) {
+            data += line.slice(6);
+        }
+    }
+    // Keepalive comments and retry hints carry no data
+    if (!data) return;
+    
+    const payload = JSON.parse(data);
+    if (name === 'meme_likes') {
+        document.querySelectorAll(`.meme-card[data-meme-id="${payload.meme_id}"] .like-button`)
+            .forEach(button => { button.textContent = `❤️ ${payload.likes}`; });
+    } else if (name === 'meme_views') {
+        document.querySelectorAll(`.meme-card[data-meme-id="${payload.meme_id}"] .view-count`)
+            .forEach(views => { views.textContent = `👁️ ${payload.views}`; });
+    } else if (name === 'meme_created') {
+        document.getElementById('newMemesNotice').style.display = 'block';
+    } else if (name === 'notification') {
+        setUnreadBadge(payload.unread_count);
+    }
+}
+
+// Switch to the following feed to show memes announced by the stream
+function showNewMemes() {
+    document.getElementById('newMemesNotice').style.display = 'none';
+    document.getElementById('searchInput').value = '';
+    document.getElementById('feedSort').disabled = false;
+    document.getElementById('feedSort').value = 'following';
+    showPage('home');
+}
+
+function escapeHtml(text) {
+    const div = document.createElement('div');
+    div.textContent = text;
+    return div.innerHTML;
+}
+
+function displayMemes(memes, container, append = false) {
     if (memes.length === 0) {
-        container.innerHTML = '<div class="loading">No memes found</div>';
+        if (!append) {
+            container.innerHTML = '<div class="loading">No memes found</div>';
+        }
         return;
     }
     
-    container.innerHTML = memes.map(meme => `
-        <div class="meme-card" onclick="viewMeme('${meme.id}')">
+    const html = memes.map(meme => `
+        <div class="meme-card" data-meme-id="${meme.id}" onclick="viewMeme('${meme.id}')">
             <img src="${meme.image_url}" alt="${meme.title}" onerror="this.src='data:image/svg+xml,%3Csvg xmlns=\\'http://www.w3.org/2000/svg\\' width=\\'300\\' height=\\'250\\'%3E%3Crect fill=\\'%23334155\\' width=\\'300\\' height=\\'250\\'/%3E%3Ctext fill=\\'%23f1f5f9\\' font-family=\\'Arial\\' font-size=\\'20\\' x=\\'50%25\\' y=\\'50%25\\' text-anchor=\\'middle\\' dy=\\'.3em\\'%3E${meme.title}%3C/text%3E%3C/svg%3E'">
             <div class="meme-info">
-                <h4>${meme.title}</h4>
+                <h4>${escapeHtml(meme.title)}</h4>
+                <div class="meme-author">by <a href="#" onclick="event.stopPropagation(); showProfile(this.dataset.username); return false;" data-username="${escapeHtml(meme.username)}">${escapeHtml(meme.username)}</a></div>
+                ${meme.highlight ? `<div class="meme-highlight">${meme.highlight}</div>` : ''}
+                ${meme.tags && meme.tags.length ? `<div class="meme-tags">${meme.tags.map(tag => `<span class="meme-tag">#${escapeHtml(tag)}</span>`).join(' ')}</div>` : ''}
                 <div class="meme-meta">
-                    <span>👁️ ${meme.views}</span>
-                    <span>❤️ ${meme.likes}</span>
+                    <span class="view-count">👁️ ${meme.views}</span>
+                    <button class="like-button ${meme.liked_by_me ? 'liked' : ''}" onclick="event.stopPropagation(); toggleLike('${meme.id}', this)">❤️ ${meme.likes}</button>
                 </div>
             </div>
         </div>
     `).join('');
+    
+    if (append) {
+        container.insertAdjacentHTML('beforeend', html);
+    } else {
+        container.innerHTML = html;
+    }
 }
 
 async function viewMeme(memeId) {
@@ -282,16 +728,23 @@ async function viewMeme(memeId) {
     }
 }
 
-async function likeMeme(memeId) {
+async function toggleLike(memeId, button) {
+    if (!authToken) {
+        showPage('login');
+        return;
+    }
+    
+    const liked = button.classList.contains('liked');
     try {
-        const response = await fetch(`${API_BASE_URL}/memes/${memeId}/like`, {
-            method: 'POST',
+        const response = await authFetch(`${API_BASE_URL}/memes/${memeId}/like`, {
+            method: liked ? 'DELETE' : 'POST',
         });
         
         const result = await response.json();
         
-        if (result.success) {
-            loadMemes(); // Refresh the list
+        if (result.success && result.data) {
+            button.classList.toggle('liked', result.data.liked);
+            button.textContent = `❤️ ${result.data.likes}`;
         }
     } catch (error) {
         console.error('Like meme error:', error);
@@ -30,7 +30,7 @@ cd ../..
 # Build Rust Backend
 echo -e "${BLUE}📦 Building Rust Backend...${NC}"
 cd backend
-cargo build --release || { echo -e "${RED}❌ Cargo build failed${NC}"; exit 1; }
+cargo build --release --features cpp-ffi || { echo -e "${RED}❌ Cargo build failed${NC}"; exit 1; }
 echo -e "${GREEN}✅ Rust Backend built successfully${NC}"
 
 # Return to root
@@ -0,0 +1,64 @@
+use std::env;
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Duration as StdDuration;
+
+use actix_web::rt;
+use chrono::Duration;
+
+/// The longest span accepted from a `*_DAYS` setting. Larger values would
+/// overflow date arithmetic, so they fall back to the default instead.
+pub const MAX_DAYS: i64 = 100 * 365;
+
+/// Parses the environment variable `key`, or `None` if it is unset or
+/// unparsable.
+pub fn read<T: FromStr>(key: &str) -> Option<T> {
+    env::var(key).ok().and_then(|v| v.parse().ok())
+}
+
+/// Reads a whole number of seconds; zero is treated as unset.
+pub fn read_seconds(key: &str) -> Option<StdDuration> {
+    read(key).filter(|&secs| secs > 0).map(StdDuration::from_secs)
+}
+
+/// Reads a whole number of days between 1 and `MAX_DAYS`.
+pub fn read_days(key: &str) -> Option<Duration> {
+    read(key)
+        .filter(|days| (1..=MAX_DAYS).contains(days))
+        .and_then(Duration::try_days)
+}
+
+/// Runs `task` every `interval` for the life of the server, starting
+/// immediately. Each run finishes before the next one starts.
+pub fn spawn_periodic<F, Fut>(interval: StdDuration, mut task: F)
+where
+    F: FnMut() -> Fut + 'static,
+    Fut: Future<Output = ()> + 'static,
+{
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(interval);
+        loop {
+            interval.tick().await;
+            task().await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_days_rejects_out_of_range_values() {
+        env::set_var("MEMEMAGE_TEST_DAYS", "7");
+        assert_eq!(read_days("MEMEMAGE_TEST_DAYS"), Some(Duration::days(7)));
+
+        for value in ["0", "-3", "36501", "9999999999999", "soon"] {
+            env::set_var("MEMEMAGE_TEST_DAYS", value);
+            assert_eq!(read_days("MEMEMAGE_TEST_DAYS"), None, "{}", value);
+        }
+
+        env::remove_var("MEMEMAGE_TEST_DAYS");
+        assert_eq!(read_days("MEMEMAGE_TEST_DAYS"), None);
+    }
+}
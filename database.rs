@@ -1,6 +1,12 @@
-use sqlx::{PgPool, postgres::PgPoolOptions};
+use sqlx::{PgConnection, PgPool, postgres::PgPoolOptions};
+use sqlx::types::Json;
 use uuid::Uuid;
-use crate::models::{User, Meme, CreateMemeRequest};
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, Utc};
+use crate::models::{AuthUser, Comment, User, Meme, MemeResponse, MemeTemplate, SearchResult, TagCount, TextBox, Upload, CreateMemeRequest, RefreshToken, UpdateProfileRequest, UserProfile, FollowUser, Notification, NotificationPreferences, UpdateNotificationPreferences};
+use crate::pagination::{Cursor, FollowCursor, MemeFilter, NotificationCursor, PageRequest};
+use crate::search::SearchRequest;
+use crate::tags::MemeTags;
 
 pub async fn create_pool(database_url: &str) -> Result<PgPool, sqlx::Error> {
     PgPoolOptions::new()
@@ -60,55 +66,711 @@ pub async fn get_user_by_email(
     Ok(user)
 }
 
+pub async fn get_user_profile(
+    pool: &PgPool,
+    username: &str,
+    viewer_id: Option<Uuid>,
+) -> Result<Option<UserProfile>, sqlx::Error> {
+    let profile = sqlx::query_as::<_, UserProfile>(
+        r#"
+        SELECT u.username, u.display_name, u.bio, u.avatar_url, u.created_at AS joined_at,
+            COUNT(m.id) AS meme_count,
+            COALESCE(SUM(m.likes), 0) AS total_likes,
+            COALESCE(SUM(m.views), 0) AS total_views,
+            (SELECT COUNT(*) FROM follows f WHERE f.followee_id = u.id) AS follower_count,
+            (SELECT COUNT(*) FROM follows f WHERE f.follower_id = u.id) AS following_count,
+            EXISTS(SELECT 1 FROM follows f WHERE f.followee_id = u.id AND f.follower_id = $2) AS followed_by_me
+        FROM users u
+        LEFT JOIN memes m ON m.user_id = u.id AND m.deleted_at IS NULL
+        WHERE u.username = $1
+        GROUP BY u.id
+        "#
+    )
+    .bind(username)
+    .bind(viewer_id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(profile)
+}
+
+/// Applies profile changes: `None` keeps a field, an empty string clears it.
+pub async fn update_user_profile(
+    pool: &PgPool,
+    user_id: Uuid,
+    changes: &UpdateProfileRequest,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE users SET
+            display_name = CASE WHEN $2::text IS NULL THEN display_name ELSE NULLIF(TRIM($2), '') END,
+            bio = CASE WHEN $3::text IS NULL THEN bio ELSE NULLIF(TRIM($3), '') END,
+            avatar_url = CASE WHEN $4::text IS NULL THEN avatar_url ELSE NULLIF($4, '') END
+        WHERE id = $1
+        "#
+    )
+    .bind(user_id)
+    .bind(&changes.display_name)
+    .bind(&changes.bio)
+    .bind(&changes.avatar_url)
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+/// Follows a user; following twice is a no-op. Returns their follower count
+/// and whether this call added the follow.
+pub async fn follow_user(
+    pool: &PgPool,
+    follower_id: Uuid,
+    followee_id: Uuid,
+) -> Result<(i64, bool), sqlx::Error> {
+    let inserted = sqlx::query(
+        "INSERT INTO follows (follower_id, followee_id) VALUES ($1, $2) ON CONFLICT DO NOTHING"
+    )
+    .bind(follower_id)
+    .bind(followee_id)
+    .execute(pool)
+    .await?;
+    
+    let followers = count_followers(pool, followee_id).await?;
+    Ok((followers, inserted.rows_affected() == 1))
+}
+
+/// Unfollows a user. Returns their follower count and whether this call
+/// removed the follow.
+pub async fn unfollow_user(
+    pool: &PgPool,
+    follower_id: Uuid,
+    followee_id: Uuid,
+) -> Result<(i64, bool), sqlx::Error> {
+    let deleted = sqlx::query("DELETE FROM follows WHERE follower_id = $1 AND followee_id = $2")
+        .bind(follower_id)
+        .bind(followee_id)
+        .execute(pool)
+        .await?;
+    
+    let followers = count_followers(pool, followee_id).await?;
+    Ok((followers, deleted.rows_affected() > 0))
+}
+
+async fn count_followers(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM follows WHERE followee_id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+}
+
+/// Who follows `user_id` (`followers = true`) or whom they follow, most
+/// recent first. Fetches one row past `limit`.
+pub async fn get_follows(
+    pool: &PgPool,
+    user_id: Uuid,
+    followers: bool,
+    after: Option<&FollowCursor>,
+    limit: i64,
+) -> Result<Vec<FollowUser>, sqlx::Error> {
+    // Followers are listed by who followed; following by who was followed
+    let (this_side, other_side) = if followers {
+        ("followee_id", "follower_id")
+    } else {
+        ("follower_id", "followee_id")
+    };
+    let sql = format!(
+        r#"
+        SELECT u.id, u.username, u.display_name, u.avatar_url, f.created_at AS followed_at
+        FROM follows f
+        JOIN users u ON u.id = f.{other}
+        WHERE f.{this} = $1
+            AND ($2::timestamptz IS NULL OR (f.created_at, u.id) < ($2, $3))
+        ORDER BY f.created_at DESC, u.id DESC
+        LIMIT $4
+        "#,
+        this = this_side,
+        other = other_side,
+    );
+    
+    let users = sqlx::query_as::<_, FollowUser>(&sql)
+        .bind(user_id)
+        .bind(after.map(|c| c.followed_at))
+        .bind(after.map(|c| c.id))
+        .bind(limit + 1)
+        .fetch_all(pool)
+        .await?;
+    
+    Ok(users)
+}
+
+/// Everyone `user_id` follows.
+pub async fn get_followee_ids(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    sqlx::query_scalar("SELECT followee_id FROM follows WHERE follower_id = $1")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+}
+
+/// Adds `actor_id` to the recipient's unread notification of this kind for
+/// this meme, creating it if needed. Repeat actors are only counted once.
+pub async fn add_notification(
+    pool: &PgPool,
+    user_id: Uuid,
+    actor_id: Uuid,
+    kind: &str,
+    meme_id: Option<Uuid>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
+    // The no-op update locks an existing unread notification and returns its id
+    let notification_id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO notifications (id, user_id, kind, meme_id, actor_id)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (user_id, kind, meme_id) WHERE read_at IS NULL
+        DO UPDATE SET actor_id = notifications.actor_id
+        RETURNING id
+        "#
+    )
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(kind)
+    .bind(meme_id)
+    .bind(actor_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    
+    let added = sqlx::query(
+        "INSERT INTO notification_actors (notification_id, actor_id) VALUES ($1, $2) ON CONFLICT DO NOTHING"
+    )
+    .bind(notification_id)
+    .bind(actor_id)
+    .execute(&mut *tx)
+    .await?;
+    
+    if added.rows_affected() == 1 {
+        sqlx::query(
+            r#"
+            UPDATE notifications
+            SET actor_id = $2, actor_count = actor_count + 1, updated_at = NOW()
+            WHERE id = $1
+            "#
+        )
+        .bind(notification_id)
+        .bind(actor_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    
+    tx.commit().await?;
+    Ok(())
+}
+
+/// A page of a user's notifications, most recently active first. Fetches one
+/// row past `limit`. Notifications about trashed memes are hidden.
+pub async fn get_notifications(
+    pool: &PgPool,
+    user_id: Uuid,
+    unread_only: bool,
+    after: Option<&NotificationCursor>,
+    limit: i64,
+) -> Result<Vec<Notification>, sqlx::Error> {
+    let notifications = sqlx::query_as::<_, Notification>(
+        r#"
+        SELECT n.id, n.kind, n.meme_id, m.title AS meme_title,
+            u.username AS actor_username, u.avatar_url AS actor_avatar_url,
+            n.actor_count, n.read_at IS NOT NULL AS read, n.updated_at
+        FROM notifications n
+        JOIN users u ON u.id = n.actor_id
+        LEFT JOIN memes m ON m.id = n.meme_id
+        WHERE n.user_id = $1
+            AND (n.meme_id IS NULL OR m.deleted_at IS NULL)
+            AND (NOT $2 OR n.read_at IS NULL)
+            AND ($3::timestamptz IS NULL OR (n.updated_at, n.id) < ($3, $4))
+        ORDER BY n.updated_at DESC, n.id DESC
+        LIMIT $5
+        "#
+    )
+    .bind(user_id)
+    .bind(unread_only)
+    .bind(after.map(|c| c.updated_at))
+    .bind(after.map(|c| c.id))
+    .bind(limit + 1)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(notifications)
+}
+
+pub async fn count_unread_notifications(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT COUNT(*)
+        FROM notifications n
+        LEFT JOIN memes m ON m.id = n.meme_id
+        WHERE n.user_id = $1 AND n.read_at IS NULL
+            AND (n.meme_id IS NULL OR m.deleted_at IS NULL)
+        "#
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+}
+
+/// Marks the given notifications, or all of them when `ids` is `None`, read.
+pub async fn mark_notifications_read(
+    pool: &PgPool,
+    user_id: Uuid,
+    ids: Option<&[Uuid]>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE notifications SET read_at = NOW()
+        WHERE user_id = $1 AND read_at IS NULL AND ($2::uuid[] IS NULL OR id = ANY($2))
+        "#
+    )
+    .bind(user_id)
+    .bind(ids)
+    .execute(pool)
+    .await?;
+    
+    Ok(result.rows_affected())
+}
+
+pub async fn get_notification_preferences(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<NotificationPreferences, sqlx::Error> {
+    let preferences = sqlx::query_as::<_, NotificationPreferences>(
+        "SELECT likes, comments, follows FROM notification_preferences WHERE user_id = $1"
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(preferences.unwrap_or_default())
+}
+
+pub async fn update_notification_preferences(
+    pool: &PgPool,
+    user_id: Uuid,
+    changes: &UpdateNotificationPreferences,
+) -> Result<NotificationPreferences, sqlx::Error> {
+    let preferences = sqlx::query_as::<_, NotificationPreferences>(
+        r#"
+        INSERT INTO notification_preferences (user_id, likes, comments, follows)
+        VALUES ($1, COALESCE($2, TRUE), COALESCE($3, TRUE), COALESCE($4, TRUE))
+        ON CONFLICT (user_id) DO UPDATE SET
+            likes = COALESCE($2, notification_preferences.likes),
+            comments = COALESCE($3, notification_preferences.comments),
+            follows = COALESCE($4, notification_preferences.follows)
+        RETURNING likes, comments, follows
+        "#
+    )
+    .bind(user_id)
+    .bind(changes.likes)
+    .bind(changes.comments)
+    .bind(changes.follows)
+    .fetch_one(pool)
+    .await?;
+    
+    Ok(preferences)
+}
+
+/// Sends `payload` to every session listening on `channel`.
+pub async fn notify_channel(
+    pool: &PgPool,
+    channel: &str,
+    payload: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(channel)
+        .bind(payload)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+/// Inserts a meme together with its tags.
+#[allow(clippy::too_many_arguments)]
 pub async fn create_meme(
     pool: &PgPool,
+    id: Uuid,
     user_id: Uuid,
     request: &CreateMemeRequest,
+    captions: &HashMap<String, String>,
+    tags: &MemeTags,
     image_url: &str,
+    source_url: &str,
+    layout: &[TextBox],
 ) -> Result<Meme, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
     let meme = sqlx::query_as::<_, Meme>(
         r#"
-        INSERT INTO memes (id, user_id, title, image_url, top_text, bottom_text, template_name, views, likes, created_at)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, 0, 0, NOW())
+        INSERT INTO memes (id, user_id, title, image_url, top_text, bottom_text, template_name, captions, overlays, source_url, layout, views, likes, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, 0, 0, NOW())
         RETURNING *
         "#
     )
-    .bind(Uuid::new_v4())
+    .bind(id)
     .bind(user_id)
     .bind(&request.title)
     .bind(image_url)
     .bind(&request.top_text)
     .bind(&request.bottom_text)
     .bind(&request.template_name)
-    .fetch_one(pool)
+    .bind(Json(captions))
+    .bind(Json(&request.overlays))
+    .bind(source_url)
+    .bind(Json(layout))
+    .fetch_one(&mut *tx)
     .await?;
     
+    if !tags.is_empty() {
+        write_meme_tags(&mut tx, id, tags).await?;
+    }
+    
+    tx.commit().await?;
     Ok(meme)
 }
 
-pub async fn get_memes(
+/// Loads the raw meme row, for ownership checks and re-rendering.
+pub async fn get_meme_row(
     pool: &PgPool,
+    id: Uuid,
+) -> Result<Option<Meme>, sqlx::Error> {
+    let meme = sqlx::query_as::<_, Meme>("SELECT * FROM memes WHERE id = $1 AND deleted_at IS NULL")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    
+    Ok(meme)
+}
+
+/// Loads a meme row that is in the trash.
+pub async fn get_trashed_meme_row(
+    pool: &PgPool,
+    id: Uuid,
+) -> Result<Option<Meme>, sqlx::Error> {
+    let meme = sqlx::query_as::<_, Meme>("SELECT * FROM memes WHERE id = $1 AND deleted_at IS NOT NULL")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    
+    Ok(meme)
+}
+
+pub async fn update_meme(
+    pool: &PgPool,
+    id: Uuid,
+    title: &str,
+    captions: &HashMap<String, String>,
+    image_url: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE memes
+        SET title = $2, captions = $3, top_text = $4, bottom_text = $5, image_url = $6
+        WHERE id = $1
+        "#
+    )
+    .bind(id)
+    .bind(title)
+    .bind(Json(captions))
+    .bind(captions.get("top"))
+    .bind(captions.get("bottom"))
+    .bind(image_url)
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+/// Replaces a meme's tags, creating any that don't exist yet.
+pub async fn set_meme_tags(
+    pool: &PgPool,
+    meme_id: Uuid,
+    tags: &MemeTags,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    write_meme_tags(&mut tx, meme_id, tags).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn write_meme_tags(
+    conn: &mut PgConnection,
+    meme_id: Uuid,
+    tags: &MemeTags,
+) -> Result<(), sqlx::Error> {
+    let names: Vec<&str> = tags.explicit.iter().chain(&tags.extracted).map(String::as_str).collect();
+    let explicit: Vec<bool> = names.iter().map(|&name| tags.explicit.iter().any(|tag| tag == name)).collect();
+    let ids: Vec<Uuid> = names.iter().map(|_| Uuid::new_v4()).collect();
+    
+    sqlx::query(
+        "INSERT INTO tags (id, name) SELECT * FROM UNNEST($1::uuid[], $2::text[]) ON CONFLICT (name) DO NOTHING"
+    )
+    .bind(&ids)
+    .bind(&names)
+    .execute(&mut *conn)
+    .await?;
+    
+    sqlx::query(
+        "DELETE FROM meme_tags WHERE meme_id = $1 \
+         AND tag_id NOT IN (SELECT id FROM tags WHERE name = ANY($2))"
+    )
+    .bind(meme_id)
+    .bind(&names)
+    .execute(&mut *conn)
+    .await?;
+    
+    sqlx::query(
+        "INSERT INTO meme_tags (meme_id, tag_id, explicit) \
+         SELECT $1, t.id, n.explicit FROM UNNEST($2::text[], $3::bool[]) AS n(name, explicit) \
+         JOIN tags t ON t.name = n.name \
+         ON CONFLICT (meme_id, tag_id) DO UPDATE SET explicit = EXCLUDED.explicit"
+    )
+    .bind(meme_id)
+    .bind(&names)
+    .bind(&explicit)
+    .execute(&mut *conn)
+    .await?;
+    
+    Ok(())
+}
+
+/// Tags the author gave a meme explicitly, leaving out those read from
+/// #hashtags in its text.
+pub async fn get_explicit_meme_tags(
+    pool: &PgPool,
+    meme_id: Uuid,
+) -> Result<Vec<String>, sqlx::Error> {
+    let tags = sqlx::query_scalar::<_, String>(
+        "SELECT t.name FROM meme_tags mt JOIN tags t ON t.id = mt.tag_id \
+         WHERE mt.meme_id = $1 AND mt.explicit ORDER BY t.name"
+    )
+    .bind(meme_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(tags)
+}
+
+/// Tags starting with `prefix`, most used first. `prefix` must already be
+/// normalized; an empty prefix matches every tag.
+pub async fn search_tags(
+    pool: &PgPool,
+    prefix: &str,
     limit: i64,
-    offset: i64,
+) -> Result<Vec<TagCount>, sqlx::Error> {
+    // Tags may contain '_', which LIKE would treat as a wildcard
+    let pattern = format!("{}%", prefix.replace('_', "\\_"));
+    let tags = sqlx::query_as::<_, TagCount>(
+        r#"
+        SELECT t.name, COUNT(*) AS meme_count
+        FROM tags t
+        JOIN meme_tags mt ON mt.tag_id = t.id
+        JOIN memes m ON m.id = mt.meme_id AND m.deleted_at IS NULL
+        WHERE t.name LIKE $1
+        GROUP BY t.name
+        ORDER BY meme_count DESC, t.name
+        LIMIT $2
+        "#
+    )
+    .bind(pattern)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(tags)
+}
+
+/// Tags on the most memes created since `since` (all time when `None`).
+pub async fn trending_tags(
+    pool: &PgPool,
+    since: Option<DateTime<Utc>>,
+    limit: i64,
+) -> Result<Vec<TagCount>, sqlx::Error> {
+    let tags = sqlx::query_as::<_, TagCount>(
+        r#"
+        SELECT t.name, COUNT(*) AS meme_count
+        FROM tags t
+        JOIN meme_tags mt ON mt.tag_id = t.id
+        JOIN memes m ON m.id = mt.meme_id AND m.deleted_at IS NULL
+        WHERE $1::timestamptz IS NULL OR m.created_at >= $1
+        GROUP BY t.name
+        ORDER BY meme_count DESC, t.name
+        LIMIT $2
+        "#
+    )
+    .bind(since)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(tags)
+}
+
+/// Moves a meme to the trash. Its comments, likes and files are kept until
+/// it is purged.
+pub async fn delete_meme(
+    pool: &PgPool,
+    id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE memes SET deleted_at = NOW() WHERE id = $1 AND deleted_at IS NULL")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+/// Takes a meme out of the trash if it was deleted less than `retention` ago.
+/// Returns false when the window has passed.
+pub async fn restore_meme(
+    pool: &PgPool,
+    id: Uuid,
+    retention: Duration,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE memes SET deleted_at = NULL WHERE id = $1 AND deleted_at > $2"
+    )
+    .bind(id)
+    .bind(Utc::now() - retention)
+    .execute(pool)
+    .await?;
+    
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn get_trashed_memes(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<MemeResponse>, sqlx::Error> {
+    let memes = sqlx::query_as::<_, MemeResponse>(&format!(
+        "{} WHERE m.user_id = $1 AND m.deleted_at IS NOT NULL ORDER BY m.deleted_at DESC",
+        MEME_RESPONSE_SELECT
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(memes)
+}
+
+/// Hard-deletes memes that have been in the trash longer than `retention`,
+/// returning the rows so their files can be removed.
+pub async fn purge_deleted_memes(
+    pool: &PgPool,
+    retention: Duration,
 ) -> Result<Vec<Meme>, sqlx::Error> {
     let memes = sqlx::query_as::<_, Meme>(
-        "SELECT * FROM memes ORDER BY created_at DESC LIMIT $1 OFFSET $2"
+        "DELETE FROM memes WHERE deleted_at <= $1 RETURNING *"
     )
-    .bind(limit)
-    .bind(offset)
+    .bind(Utc::now() - retention)
     .fetch_all(pool)
     .await?;
     
     Ok(memes)
 }
 
+/// Selects `MemeResponse` rows: the meme, its author and tags, and whether
+/// the viewer bound as `$1` (NULL when anonymous) has liked it.
+const MEME_RESPONSE_SELECT: &str = r#"
+    SELECT m.id, m.title, m.image_url, m.top_text, m.bottom_text, m.template_name,
+        m.captions, m.overlays,
+        ARRAY(SELECT t.name FROM meme_tags mt JOIN tags t ON t.id = mt.tag_id
+            WHERE mt.meme_id = m.id ORDER BY t.name) AS tags, m.views, m.likes, m.hot_score, m.rising_score, m.created_at,
+        m.deleted_at, u.username, u.avatar_url,
+        EXISTS(SELECT 1 FROM user_meme_likes l WHERE l.meme_id = m.id AND l.user_id = $1) AS liked_by_me,
+        (SELECT COUNT(*) FROM comments c WHERE c.meme_id = m.id) AS comment_count
+    FROM memes m
+    JOIN users u ON u.id = m.user_id
+"#;
+
+/// One page of the feed, or of `author_id`'s memes. Fetches one row past
+/// `page.limit` so the caller can tell whether another page follows.
+pub async fn get_memes(
+    pool: &PgPool,
+    viewer_id: Option<Uuid>,
+    filter: &MemeFilter,
+    page: &PageRequest,
+) -> Result<Vec<MemeResponse>, sqlx::Error> {
+    let mut sql = format!("{} WHERE m.deleted_at IS NULL", MEME_RESPONSE_SELECT);
+    let mut param = 2;
+    if filter.author_id.is_some() {
+        sql.push_str(&format!(" AND m.user_id = ${}", param));
+        param += 1;
+    }
+    if filter.followed_by.is_some() {
+        sql.push_str(&format!(
+            " AND m.user_id IN (SELECT followee_id FROM follows WHERE follower_id = ${})",
+            param
+        ));
+        param += 1;
+    }
+    if filter.tag.is_some() {
+        sql.push_str(&format!(
+            " AND m.id IN (SELECT mt.meme_id FROM meme_tags mt JOIN tags t ON t.id = mt.tag_id WHERE t.name = ${})",
+            param
+        ));
+        param += 1;
+    }
+    let since = page.window.since(Utc::now());
+    if since.is_some() {
+        sql.push_str(&format!(" AND m.created_at >= ${}", param));
+        param += 1;
+    }
+    if page.after.is_some() {
+        sql.push_str(&format!(" AND ({}) < (${}, ${})", page.sort.key_columns(), param, param + 1));
+        param += 2;
+    }
+    sql.push_str(&format!(" ORDER BY {} LIMIT ${}", page.sort.order_by(), param));
+    
+    let mut query = sqlx::query_as::<_, MemeResponse>(&sql).bind(viewer_id);
+    if let Some(author_id) = filter.author_id {
+        query = query.bind(author_id);
+    }
+    if let Some(follower_id) = filter.followed_by {
+        query = query.bind(follower_id);
+    }
+    if let Some(tag) = &filter.tag {
+        query = query.bind(tag);
+    }
+    if let Some(since) = since {
+        query = query.bind(since);
+    }
+    query = match page.after {
+        Some(Cursor::New { created_at, id }) => query.bind(created_at).bind(id),
+        Some(Cursor::Top { likes, id }) => query.bind(likes).bind(id),
+        Some(Cursor::Hot { score, id } | Cursor::Rising { score, id }) => query.bind(score).bind(id),
+        None => query,
+    };
+    
+    let memes = query.bind(page.limit + 1).fetch_all(pool).await?;
+    Ok(memes)
+}
+
 pub async fn get_meme_by_id(
     pool: &PgPool,
     id: Uuid,
-) -> Result<Option<Meme>, sqlx::Error> {
-    let meme = sqlx::query_as::<_, Meme>(
-        "SELECT * FROM memes WHERE id = $1"
-    )
+    viewer_id: Option<Uuid>,
+) -> Result<Option<MemeResponse>, sqlx::Error> {
+    let meme = sqlx::query_as::<_, MemeResponse>(&format!(
+        "{} WHERE m.id = $2 AND m.deleted_at IS NULL",
+        MEME_RESPONSE_SELECT
+    ))
+    .bind(viewer_id)
     .bind(id)
     .fetch_optional(pool)
     .await?;
@@ -116,37 +778,583 @@ pub async fn get_meme_by_id(
     Ok(meme)
 }
 
-pub async fn get_user_memes(
+/// Ranked full-text search over live memes. Fetches one row past
+/// `search.limit` so the caller can tell whether another page follows.
+pub async fn search_memes(
+    pool: &PgPool,
+    viewer_id: Option<Uuid>,
+    search: &SearchRequest,
+) -> Result<Vec<SearchResult>, sqlx::Error> {
+    let mut filters = String::new();
+    let mut param = 3;
+    if search.template.is_some() {
+        filters.push_str(&format!(" AND m.template_name = ${}", param));
+        param += 1;
+    }
+    if search.author.is_some() {
+        filters.push_str(&format!(" AND m.user_id = (SELECT id FROM users WHERE username = ${})", param));
+        param += 1;
+    }
+    if search.from.is_some() {
+        filters.push_str(&format!(" AND m.created_at >= ${}", param));
+        param += 1;
+    }
+    if search.to.is_some() {
+        filters.push_str(&format!(" AND m.created_at < ${}", param));
+        param += 1;
+    }
+    if search.after.is_some() {
+        filters.push_str(&format!(
+            " AND (ts_rank_cd(m.search_vector, q.query), m.id) < (${}, ${})",
+            param,
+            param + 1
+        ));
+        param += 2;
+    }
+    
+    // Rank and page on ids first, then build snippets for that page only.
+    // The snippet text is escaped before <mark> tags are added.
+    let sql = format!(
+        r#"
+        WITH q AS (SELECT to_tsquery('english', $2) AS query),
+        page AS (
+            SELECT m.id, ts_rank_cd(m.search_vector, q.query) AS rank
+            FROM memes m, q
+            WHERE m.deleted_at IS NULL AND m.search_vector @@ q.query{filters}
+            ORDER BY rank DESC, m.id DESC
+            LIMIT ${limit}
+        )
+        SELECT r.*, page.rank,
+            ts_headline('english',
+                replace(replace(replace(
+                    concat_ws(' ', r.title, meme_caption_text(r.top_text, r.bottom_text, r.captions)),
+                    '&', '&amp;'), '<', '&lt;'), '>', '&gt;'),
+                q.query,
+                'StartSel=<mark>, StopSel=</mark>, MaxWords=20, MinWords=5, MaxFragments=2'
+            ) AS highlight
+        FROM ({select}) r
+        JOIN page ON page.id = r.id
+        CROSS JOIN q
+        ORDER BY page.rank DESC, r.id DESC
+        "#,
+        filters = filters,
+        limit = param,
+        select = MEME_RESPONSE_SELECT,
+    );
+    
+    let mut query = sqlx::query_as::<_, SearchResult>(&sql)
+        .bind(viewer_id)
+        .bind(&search.tsquery);
+    if let Some(template) = &search.template {
+        query = query.bind(template);
+    }
+    if let Some(author) = &search.author {
+        query = query.bind(author);
+    }
+    if let Some(from) = search.from {
+        query = query.bind(from);
+    }
+    if let Some(to) = search.to {
+        query = query.bind(to);
+    }
+    if let Some(after) = &search.after {
+        query = query.bind(after.rank).bind(after.id);
+    }
+    
+    let results = query.bind(search.limit + 1).fetch_all(pool).await?;
+    Ok(results)
+}
+
+/// Recomputes `hot_score` and `rising_score` for memes from the last
+/// `horizon`, and zeroes them for older ones. Returns the rows updated.
+pub async fn refresh_meme_scores(
     pool: &PgPool,
+    horizon: Duration,
+) -> Result<u64, sqlx::Error> {
+    let cutoff = Utc::now() - horizon;
+    
+    // hot: all engagement, decayed by age. rising: engagement from the last
+    // six hours, decayed more gently so new activity on young memes wins.
+    let updated = sqlx::query(r#"
+        WITH stats AS (
+            SELECT m.id,
+                m.likes + 2 * (SELECT COUNT(*) FROM comments c WHERE c.meme_id = m.id)
+                    + m.views / 10.0 AS engagement,
+                (SELECT COUNT(*) FROM user_meme_likes l
+                    WHERE l.meme_id = m.id AND l.created_at > NOW() - INTERVAL '6 hours')
+                    + 2 * (SELECT COUNT(*) FROM comments c
+                    WHERE c.meme_id = m.id AND c.created_at > NOW() - INTERVAL '6 hours') AS recent,
+                EXTRACT(EPOCH FROM NOW() - m.created_at) / 3600.0 AS age_hours
+            FROM memes m
+            WHERE m.deleted_at IS NULL AND m.created_at >= $1
+        )
+        UPDATE memes SET
+            hot_score = (stats.engagement / POWER(stats.age_hours + 2, 1.8))::DOUBLE PRECISION,
+            rising_score = (stats.recent / SQRT(stats.age_hours + 2))::DOUBLE PRECISION
+        FROM stats
+        WHERE memes.id = stats.id
+    "#)
+    .bind(cutoff)
+    .execute(pool)
+    .await?
+    .rows_affected();
+    
+    let zeroed = sqlx::query(
+        "UPDATE memes SET hot_score = 0, rising_score = 0 \
+         WHERE created_at < $1 AND (hot_score <> 0 OR rising_score <> 0)"
+    )
+    .bind(cutoff)
+    .execute(pool)
+    .await?
+    .rows_affected();
+    
+    Ok(updated + zeroed)
+}
+
+/// Counts a view and returns the new total.
+pub async fn increment_meme_views(
+    pool: &PgPool,
+    id: Uuid,
+) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar("UPDATE memes SET views = views + 1 WHERE id = $1 RETURNING views")
+        .bind(id)
+        .fetch_one(pool)
+        .await
+}
+
+/// Records a like by `user_id`, counting it only once. Returns the new like
+/// count and whether this call added the like, or `None` if the meme doesn't
+/// exist.
+pub async fn like_meme(
+    pool: &PgPool,
+    meme_id: Uuid,
     user_id: Uuid,
-) -> Result<Vec<Meme>, sqlx::Error> {
-    let memes = sqlx::query_as::<_, Meme>(
-        "SELECT * FROM memes WHERE user_id = $1 ORDER BY created_at DESC"
+) -> Result<Option<(i32, bool)>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
+    // Lock the row so concurrent likes and unlikes serialize on the counter
+    let exists = sqlx::query("SELECT 1 FROM memes WHERE id = $1 AND deleted_at IS NULL FOR UPDATE")
+        .bind(meme_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if exists.is_none() {
+        return Ok(None);
+    }
+    
+    let inserted = sqlx::query(
+        "INSERT INTO user_meme_likes (user_id, meme_id, created_at) VALUES ($1, $2, NOW()) ON CONFLICT DO NOTHING"
     )
     .bind(user_id)
+    .bind(meme_id)
+    .execute(&mut *tx)
+    .await?;
+    
+    let likes: i32 = sqlx::query_scalar(
+        "UPDATE memes SET likes = likes + $2 WHERE id = $1 RETURNING likes"
+    )
+    .bind(meme_id)
+    .bind(inserted.rows_affected() as i32)
+    .fetch_one(&mut *tx)
+    .await?;
+    
+    tx.commit().await?;
+    Ok(Some((likes, inserted.rows_affected() == 1)))
+}
+
+/// Removes a like by `user_id`. Returns the new like count, or `None` if the
+/// meme doesn't exist.
+pub async fn unlike_meme(
+    pool: &PgPool,
+    meme_id: Uuid,
+    user_id: Uuid,
+) -> Result<Option<i32>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
+    let exists = sqlx::query("SELECT 1 FROM memes WHERE id = $1 AND deleted_at IS NULL FOR UPDATE")
+        .bind(meme_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if exists.is_none() {
+        return Ok(None);
+    }
+    
+    let deleted = sqlx::query("DELETE FROM user_meme_likes WHERE user_id = $1 AND meme_id = $2")
+        .bind(user_id)
+        .bind(meme_id)
+        .execute(&mut *tx)
+        .await?;
+    
+    let likes: i32 = sqlx::query_scalar(
+        "UPDATE memes SET likes = GREATEST(likes - $2, 0) WHERE id = $1 RETURNING likes"
+    )
+    .bind(meme_id)
+    .bind(deleted.rows_affected() as i32)
+    .fetch_one(&mut *tx)
+    .await?;
+    
+    tx.commit().await?;
+    Ok(Some(likes))
+}
+
+pub async fn get_template_by_name(
+    pool: &PgPool,
+    name: &str,
+) -> Result<Option<MemeTemplate>, sqlx::Error> {
+    let template = sqlx::query_as::<_, MemeTemplate>(
+        "SELECT * FROM meme_templates WHERE name = $1"
+    )
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(template)
+}
+
+pub async fn get_template_by_id(
+    pool: &PgPool,
+    id: Uuid,
+) -> Result<Option<MemeTemplate>, sqlx::Error> {
+    let template = sqlx::query_as::<_, MemeTemplate>(
+        "SELECT * FROM meme_templates WHERE id = $1"
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(template)
+}
+
+pub async fn get_templates(
+    pool: &PgPool,
+    search: Option<&str>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<MemeTemplate>, sqlx::Error> {
+    // Escape LIKE wildcards so the search term is matched literally
+    let pattern = search.map(|q| {
+        let escaped = q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        format!("%{}%", escaped)
+    });
+    
+    let templates = sqlx::query_as::<_, MemeTemplate>(
+        r#"
+        SELECT * FROM meme_templates
+        WHERE $1::TEXT IS NULL OR name ILIKE $1 OR description ILIKE $1
+        ORDER BY usage_count DESC, name ASC
+        LIMIT $2 OFFSET $3
+        "#
+    )
+    .bind(pattern)
+    .bind(limit)
+    .bind(offset)
     .fetch_all(pool)
     .await?;
     
-    Ok(memes)
+    Ok(templates)
 }
 
-pub async fn increment_meme_views(
+pub async fn create_template(
+    pool: &PgPool,
+    id: Uuid,
+    name: &str,
+    description: Option<&str>,
+    layout: Option<&[TextBox]>,
+    image_url: &str,
+) -> Result<MemeTemplate, sqlx::Error> {
+    let template = sqlx::query_as::<_, MemeTemplate>(
+        r#"
+        INSERT INTO meme_templates (id, name, image_url, description, layout, usage_count, created_at)
+        VALUES ($1, $2, $3, $4, $5, 0, NOW())
+        RETURNING *
+        "#
+    )
+    .bind(id)
+    .bind(name)
+    .bind(image_url)
+    .bind(description)
+    .bind(layout.map(Json))
+    .fetch_one(pool)
+    .await?;
+    
+    Ok(template)
+}
+
+pub async fn increment_template_usage(
+    pool: &PgPool,
+    id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE meme_templates SET usage_count = usage_count + 1 WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn create_upload(
+    pool: &PgPool,
+    id: Uuid,
+    user_id: Uuid,
+    image_url: &str,
+    format: &str,
+    width: i32,
+    height: i32,
+    size_bytes: i64,
+) -> Result<Upload, sqlx::Error> {
+    let upload = sqlx::query_as::<_, Upload>(
+        r#"
+        INSERT INTO uploads (id, user_id, image_url, format, width, height, size_bytes, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, NOW())
+        RETURNING *
+        "#
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(image_url)
+    .bind(format)
+    .bind(width)
+    .bind(height)
+    .bind(size_bytes)
+    .fetch_one(pool)
+    .await?;
+    
+    Ok(upload)
+}
+
+pub async fn get_upload(
+    pool: &PgPool,
+    id: Uuid,
+    user_id: Uuid,
+) -> Result<Option<Upload>, sqlx::Error> {
+    let upload = sqlx::query_as::<_, Upload>(
+        "SELECT * FROM uploads WHERE id = $1 AND user_id = $2"
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(upload)
+}
+
+pub async fn create_session(
+    pool: &PgPool,
+    id: Uuid,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO sessions (id, user_id, created_at) VALUES ($1, $2, NOW())")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+/// Loads the user of a session, or `None` once the session is revoked.
+pub async fn get_session_user(
+    pool: &PgPool,
+    session_id: Uuid,
+) -> Result<Option<AuthUser>, sqlx::Error> {
+    let user = sqlx::query_as::<_, AuthUser>(
+        r#"
+        SELECT u.id, u.username, u.roles, s.id AS session_id
+        FROM sessions s
+        JOIN users u ON u.id = s.user_id
+        WHERE s.id = $1 AND s.revoked_at IS NULL
+        "#
+    )
+    .bind(session_id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(user)
+}
+
+pub async fn revoke_session(
+    pool: &PgPool,
+    id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE sessions SET revoked_at = NOW() WHERE id = $1 AND revoked_at IS NULL")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+pub async fn revoke_user_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("UPDATE sessions SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected())
+}
+
+pub async fn create_refresh_token(
+    pool: &PgPool,
+    session_id: Uuid,
+    token_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO refresh_tokens (id, session_id, token_hash, expires_at, created_at)
+        VALUES ($1, $2, $3, $4, NOW())
+        "#
+    )
+    .bind(Uuid::new_v4())
+    .bind(session_id)
+    .bind(token_hash)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+pub async fn get_refresh_token(
+    pool: &PgPool,
+    token_hash: &str,
+) -> Result<Option<RefreshToken>, sqlx::Error> {
+    let token = sqlx::query_as::<_, RefreshToken>(
+        r#"
+        SELECT rt.id, rt.session_id, s.user_id, u.username, rt.expires_at, rt.used_at, s.revoked_at
+        FROM refresh_tokens rt
+        JOIN sessions s ON s.id = rt.session_id
+        JOIN users u ON u.id = s.user_id
+        WHERE rt.token_hash = $1
+        "#
+    )
+    .bind(token_hash)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(token)
+}
+
+/// Marks a refresh token as used. Returns false if it already was, which
+/// means another request got there first.
+pub async fn use_refresh_token(
+    pool: &PgPool,
+    id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE refresh_tokens SET used_at = NOW() WHERE id = $1 AND used_at IS NULL")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected() == 1)
+}
+
+const COMMENT_COLUMNS: &str = r#"
+    c.id, c.meme_id, c.user_id, u.username, u.avatar_url, c.parent_id, c.content,
+    (SELECT COUNT(*) FROM comments r WHERE r.parent_id = c.id) AS reply_count,
+    c.created_at, c.updated_at
+"#;
+
+/// Lists a meme's comments oldest first: top-level ones, or the replies to
+/// `parent_id`.
+pub async fn get_comments(
+    pool: &PgPool,
+    meme_id: Uuid,
+    parent_id: Option<Uuid>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Comment>, sqlx::Error> {
+    let comments = sqlx::query_as::<_, Comment>(&format!(
+        r#"
+        SELECT {}
+        FROM comments c
+        JOIN users u ON u.id = c.user_id
+        WHERE c.meme_id = $1 AND c.parent_id IS NOT DISTINCT FROM $2
+        ORDER BY c.created_at, c.id
+        LIMIT $3 OFFSET $4
+        "#,
+        COMMENT_COLUMNS
+    ))
+    .bind(meme_id)
+    .bind(parent_id)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(comments)
+}
+
+/// Finds a comment, hiding those on memes in the trash.
+pub async fn get_comment_by_id(
+    pool: &PgPool,
+    id: Uuid,
+) -> Result<Option<Comment>, sqlx::Error> {
+    let comment = sqlx::query_as::<_, Comment>(&format!(
+        r#"
+        SELECT {}
+        FROM comments c
+        JOIN users u ON u.id = c.user_id
+        JOIN memes m ON m.id = c.meme_id
+        WHERE c.id = $1 AND m.deleted_at IS NULL
+        "#,
+        COMMENT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(comment)
+}
+
+pub async fn create_comment(
+    pool: &PgPool,
+    id: Uuid,
+    meme_id: Uuid,
+    user_id: Uuid,
+    parent_id: Option<Uuid>,
+    content: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO comments (id, meme_id, user_id, parent_id, content, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, NOW(), NOW())
+        "#
+    )
+    .bind(id)
+    .bind(meme_id)
+    .bind(user_id)
+    .bind(parent_id)
+    .bind(content)
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+pub async fn update_comment(
     pool: &PgPool,
     id: Uuid,
+    content: &str,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query("UPDATE memes SET views = views + 1 WHERE id = $1")
+    sqlx::query("UPDATE comments SET content = $2 WHERE id = $1")
         .bind(id)
+        .bind(content)
         .execute(pool)
         .await?;
     
     Ok(())
 }
 
-pub async fn increment_meme_likes(
+/// Deletes a comment together with its replies.
+pub async fn delete_comment(
     pool: &PgPool,
     id: Uuid,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query("UPDATE memes SET likes = likes + 1 WHERE id = $1")
+    sqlx::query("DELETE FROM comments WHERE id = $1")
         .bind(id)
         .execute(pool)
         .await?;
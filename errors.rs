@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpResponse, ResponseError};
+use validator::{ValidationError, ValidationErrors, ValidationErrorsKind};
+
+use crate::image_limits::ImageRejection;
+use crate::models::{ApiResponse, FieldError};
+use crate::renderer::RenderError;
+use crate::uploads::UploadError;
+
+/// Error type returned by every handler.
+///
+/// Client-facing variants carry a message that is safe to show. Server-side
+/// failures (`Render`, `Database`, `Internal`) are logged in full and reach
+/// the client only as a generic message and code.
+#[derive(Debug)]
+pub enum AppError {
+    Validation(ValidationErrors),
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    PayloadTooLarge(String),
+    UnsupportedMediaType(String),
+    Unprocessable(String),
+    Render(String),
+    Database(sqlx::Error),
+    Internal(String),
+}
+
+impl AppError {
+    /// Machine-readable code sent in `ApiResponse::code`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Validation(_) => "validation_failed",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::PayloadTooLarge(_) => "payload_too_large",
+            AppError::UnsupportedMediaType(_) => "unsupported_media_type",
+            AppError::Unprocessable(_) => "unprocessable_image",
+            AppError::Render(_) => "render_failed",
+            AppError::Database(_) => "database_error",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+
+    fn client_message(&self) -> String {
+        match self {
+            AppError::Validation(_) => "Validation failed".to_string(),
+            AppError::Render(_) => "Failed to render meme".to_string(),
+            AppError::Database(_) | AppError::Internal(_) => "Internal server error".to_string(),
+            AppError::BadRequest(msg)
+            | AppError::Unauthorized(msg)
+            | AppError::Forbidden(msg)
+            | AppError::NotFound(msg)
+            | AppError::Conflict(msg)
+            | AppError::PayloadTooLarge(msg)
+            | AppError::UnsupportedMediaType(msg)
+            | AppError::Unprocessable(msg) => msg.clone(),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Validation(errors) => write!(f, "Validation failed: {}", errors),
+            AppError::Render(msg) => write!(f, "Render failed: {}", msg),
+            AppError::Database(e) => write!(f, "Database error: {}", e),
+            AppError::Internal(msg) => write!(f, "Internal error: {}", msg),
+            other => write!(f, "{}", other.client_message()),
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::Validation(_) | AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Render(_) | AppError::Database(_) | AppError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        if status.is_server_error() {
+            log::error!("{}", self);
+        }
+
+        let mut body = ApiResponse::<()>::error_with_code(self.code(), self.client_message());
+        if let AppError::Validation(errors) = self {
+            body.errors = Some(field_errors(errors));
+        }
+
+        let mut response = HttpResponse::build(status);
+        if let AppError::Unauthorized(_) = self {
+            response.insert_header((header::WWW_AUTHENTICATE, r#"Bearer realm="mememage""#));
+        }
+        response.json(body)
+    }
+}
+
+/// Flattens `ValidationErrors` into field paths such as `title` or
+/// `overlays[2].color`, each with its list of failed rules.
+pub fn field_errors(errors: &ValidationErrors) -> HashMap<String, Vec<FieldError>> {
+    let mut fields = HashMap::new();
+    collect_field_errors(errors, "", &mut fields);
+    fields
+}
+
+fn collect_field_errors(
+    errors: &ValidationErrors,
+    prefix: &str,
+    fields: &mut HashMap<String, Vec<FieldError>>,
+) {
+    for (field, kind) in errors.errors() {
+        let path = if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", prefix, field)
+        };
+
+        match kind {
+            ValidationErrorsKind::Field(failures) => {
+                fields
+                    .entry(path)
+                    .or_default()
+                    .extend(failures.iter().map(field_error));
+            }
+            ValidationErrorsKind::Struct(nested) => collect_field_errors(nested, &path, fields),
+            ValidationErrorsKind::List(items) => {
+                for (index, nested) in items {
+                    collect_field_errors(nested, &format!("{}[{}]", path, index), fields);
+                }
+            }
+        }
+    }
+}
+
+fn field_error(error: &ValidationError) -> FieldError {
+    // validator records the rejected input as `value`; never echo it back,
+    // it may be a password
+    let params: HashMap<String, serde_json::Value> = error
+        .params
+        .iter()
+        .filter(|(name, _)| *name != "value")
+        .map(|(name, value)| (name.to_string(), value.clone()))
+        .collect();
+
+    let message = match &error.message {
+        Some(message) => message.to_string(),
+        None => default_message(error),
+    };
+
+    FieldError {
+        code: error.code.to_string(),
+        message,
+        params,
+    }
+}
+
+/// English fallback for rules that don't set their own message. Clients can
+/// localize from `code` and `params` instead.
+fn default_message(error: &ValidationError) -> String {
+    let param = |name: &str| error.params.get(name).map(|v| v.to_string());
+    let unit = match error.params.get("value") {
+        Some(serde_json::Value::Array(_)) | Some(serde_json::Value::Object(_)) => "items",
+        _ => "characters",
+    };
+
+    match error.code.as_ref() {
+        "length" => match (param("min"), param("max"), param("equal")) {
+            (_, _, Some(equal)) => format!("Must be exactly {} {}", equal, unit),
+            (Some(min), Some(max), _) => format!("Must be between {} and {} {}", min, max, unit),
+            (Some(min), None, _) if min == "1" => "Must not be empty".to_string(),
+            (Some(min), None, _) => format!("Must be at least {} {}", min, unit),
+            (None, Some(max), _) => format!("Must be at most {} {}", max, unit),
+            _ => "Has an invalid length".to_string(),
+        },
+        "range" => match (param("min"), param("max")) {
+            (Some(min), Some(max)) => format!("Must be between {} and {}", min, max),
+            (Some(min), None) => format!("Must be at least {}", min),
+            (None, Some(max)) => format!("Must be at most {}", max),
+            _ => "Is out of range".to_string(),
+        },
+        "email" => "Must be a valid email address".to_string(),
+        "invalid_color" => "Must be a color name or #RRGGBB / #RRGGBBAA".to_string(),
+        "invalid_box_name" => "Text box names must be 1 to 50 characters".to_string(),
+        "duplicate_box_name" => "Text box names must be unique".to_string(),
+        "box_out_of_bounds" => "Text boxes must lie within the image".to_string(),
+        "invalid_box_style" => "Text box font size and rotation must be valid numbers".to_string(),
+        "captions_too_long" => "At most 20 captions of up to 500 characters each".to_string(),
+        "invalid_avatar_url" => "Must be an http(s) URL or an uploaded image".to_string(),
+        "invalid_tags" => "At most 10 tags of 1 to 30 letters, digits or underscores".to_string(),
+        _ => "Is invalid".to_string(),
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(error: sqlx::Error) -> Self {
+        // Unique violations surface as conflicts rather than server errors
+        if let sqlx::Error::Database(db_error) = &error {
+            if db_error.code().as_deref() == Some("23505") {
+                return AppError::Conflict("Resource already exists".to_string());
+            }
+        }
+        AppError::Database(error)
+    }
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(errors: ValidationErrors) -> Self {
+        AppError::Validation(errors)
+    }
+}
+
+impl From<UploadError> for AppError {
+    fn from(error: UploadError) -> Self {
+        match error {
+            UploadError::TooLarge(msg) => AppError::PayloadTooLarge(msg),
+            UploadError::Unsupported(msg) => AppError::UnsupportedMediaType(msg),
+            UploadError::Invalid(msg) => AppError::BadRequest(msg),
+            UploadError::Malformed(msg) => AppError::Unprocessable(msg),
+            UploadError::Io(msg) => AppError::Internal(msg),
+        }
+    }
+}
+
+impl From<ImageRejection> for AppError {
+    fn from(rejection: ImageRejection) -> Self {
+        UploadError::from(rejection).into()
+    }
+}
+
+impl From<RenderError> for AppError {
+    fn from(error: RenderError) -> Self {
+        match error {
+            RenderError::Rejected(rejection) => rejection.into(),
+            RenderError::Failed(msg) => AppError::Render(msg),
+        }
+    }
+}
+
+impl From<bcrypt::BcryptError> for AppError {
+    fn from(error: bcrypt::BcryptError) -> Self {
+        AppError::Internal(format!("Password hashing failed: {}", error))
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for AppError {
+    fn from(error: jsonwebtoken::errors::Error) -> Self {
+        AppError::Internal(format!("Token creation failed: {}", error))
+    }
+}
+
+impl From<actix_web::error::BlockingError> for AppError {
+    fn from(error: actix_web::error::BlockingError) -> Self {
+        AppError::Internal(error.to_string())
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(error: std::io::Error) -> Self {
+        AppError::Internal(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::MessageBody;
+
+    #[test]
+    fn test_internal_errors_are_not_leaked() {
+        let error = AppError::Database(sqlx::Error::PoolTimedOut);
+        assert_eq!(error.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let body = error.error_response().into_body().try_into_bytes().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["code"], "database_error");
+        assert_eq!(json["error"], "Internal server error");
+    }
+
+    #[test]
+    fn test_unauthorized_sets_www_authenticate() {
+        let response = AppError::Unauthorized("Invalid or expired token".into()).error_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers().get(header::WWW_AUTHENTICATE).unwrap(),
+            r#"Bearer realm="mememage""#
+        );
+    }
+
+    #[test]
+    fn test_validation_errors_are_keyed_by_field() {
+        let mut errors = ValidationErrors::new();
+        let mut length = ValidationError::new("length");
+        length.add_param("min".into(), &8);
+        length.add_param("value".into(), &"hunter2");
+        errors.add("password", length);
+
+        let mut overlay = ValidationErrors::new();
+        overlay.add("color", ValidationError::new("invalid_color"));
+        let items = [(1, Box::new(overlay))].into_iter().collect();
+        errors.errors_mut().insert("overlays", ValidationErrorsKind::List(items));
+
+        let fields = field_errors(&errors);
+        let password = &fields["password"][0];
+        assert_eq!(password.code, "length");
+        assert_eq!(password.message, "Must be at least 8 characters");
+        assert_eq!(password.params.get("min"), Some(&serde_json::json!(8)));
+        assert!(!password.params.contains_key("value"));
+        assert_eq!(fields["overlays[1].color"][0].code, "invalid_color");
+    }
+
+    #[test]
+    fn test_render_rejections_map_to_client_errors() {
+        let too_large: AppError = RenderError::Rejected(ImageRejection::TooLarge("big".into())).into();
+        assert_eq!(too_large.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let malformed: AppError = RenderError::Rejected(ImageRejection::Malformed("bad".into())).into();
+        assert_eq!(malformed.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
@@ -1,14 +1,24 @@
-use actix_web::{web, HttpResponse, HttpRequest};
+use actix_multipart::Multipart;
+use actix_web::{http::header, web, HttpResponse};
 use sqlx::PgPool;
 use uuid::Uuid;
 use validator::Validate;
 use std::fs;
-use std::path::Path;
+use chrono::Utc;
 
 use crate::models::*;
 use crate::database;
-use crate::auth::{hash_password, verify_password, create_jwt, decode_jwt, extract_token_from_header};
-use crate::image_ffi::MemeProcessor;
+use crate::auth::{hash_password, verify_password, generate_refresh_token, hash_refresh_token, JwtKeys, OptionalAuthUser};
+use crate::errors::AppError;
+use crate::image_limits::ImageLimits;
+use crate::renderer::{self, MemeRenderer};
+use crate::notifications::{self, NotificationKind};
+use crate::pagination::{decode_cursor, encode_cursor, finish_page, page_size, FollowCursor, MemeFilter, MemeWindow, NotificationCursor, PageRequest, MAX_PAGE_SIZE};
+use crate::realtime::{self, Event, EventHub, StreamSession, Subscriber};
+use crate::search::SearchRequest;
+use crate::tags;
+use crate::trash::TrashConfig;
+use crate::uploads;
 
 // Health check endpoint
 pub async fn health_check() -> HttpResponse {
@@ -18,285 +28,935 @@ pub async fn health_check() -> HttpResponse {
 // User signup
 pub async fn signup(
     pool: web::Data<PgPool>,
+    jwt_keys: web::Data<JwtKeys>,
     user_data: web::Json<UserSignup>,
-) -> HttpResponse {
+) -> Result<HttpResponse, AppError> {
     // Validate input
-    if let Err(errors) = user_data.validate() {
-        return HttpResponse::BadRequest().json(ApiResponse::<()>::error(
-            format!("Validation error: {:?}", errors)
-        ));
-    }
+    user_data.validate()?;
     
     // Check if username already exists
-    match database::get_user_by_username(&pool, &user_data.username).await {
-        Ok(Some(_)) => {
-            return HttpResponse::Conflict().json(ApiResponse::<()>::error(
-                "Username already exists".to_string()
-            ));
-        }
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
-                format!("Database error: {}", e)
-            ));
-        }
-        _ => {}
+    if database::get_user_by_username(&pool, &user_data.username).await?.is_some() {
+        return Err(AppError::Conflict("Username already exists".to_string()));
     }
     
     // Check if email already exists
-    match database::get_user_by_email(&pool, &user_data.email).await {
-        Ok(Some(_)) => {
-            return HttpResponse::Conflict().json(ApiResponse::<()>::error(
-                "Email already exists".to_string()
-            ));
-        }
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
-                format!("Database error: {}", e)
-            ));
-        }
-        _ => {}
+    if database::get_user_by_email(&pool, &user_data.email).await?.is_some() {
+        return Err(AppError::Conflict("Email already exists".to_string()));
     }
     
     // Hash password
-    let password_hash = match hash_password(&user_data.password) {
-        Ok(hash) => hash,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
-                format!("Failed to hash password: {}", e)
-            ));
-        }
-    };
+    let password_hash = hash_password(&user_data.password)?;
     
     // Create user
-    match database::create_user(&pool, &user_data.username, &user_data.email, &password_hash).await {
-        Ok(user) => {
-            let token = match create_jwt(&user.id.to_string(), &user.username) {
-                Ok(t) => t,
-                Err(e) => {
-                    return HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
-                        format!("Failed to create token: {}", e)
-                    ));
-                }
-            };
-            
-            HttpResponse::Created().json(ApiResponse::success(AuthResponse {
-                token,
-                user: user.into(),
-            }))
-        }
-        Err(e) => {
-            HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
-                format!("Failed to create user: {}", e)
-            ))
-        }
-    }
+    let user = database::create_user(&pool, &user_data.username, &user_data.email, &password_hash).await?;
+    let tokens = start_session(&pool, &jwt_keys, user.id, &user.username).await?;
+    
+    Ok(HttpResponse::Created().json(ApiResponse::success(AuthResponse {
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
+        user: user.into(),
+    })))
 }
 
 // User login
 pub async fn login(
     pool: web::Data<PgPool>,
+    jwt_keys: web::Data<JwtKeys>,
     login_data: web::Json<UserLogin>,
-) -> HttpResponse {
+) -> Result<HttpResponse, AppError> {
     // Validate input
-    if let Err(errors) = login_data.validate() {
-        return HttpResponse::BadRequest().json(ApiResponse::<()>::error(
-            format!("Validation error: {:?}", errors)
-        ));
-    }
+    login_data.validate()?;
     
     // Get user
-    let user = match database::get_user_by_username(&pool, &login_data.username).await {
-        Ok(Some(user)) => user,
-        Ok(None) => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
-                "Invalid credentials".to_string()
-            ));
-        }
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
-                format!("Database error: {}", e)
-            ));
-        }
-    };
+    let user = database::get_user_by_username(&pool, &login_data.username)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid credentials".to_string()))?;
     
     // Verify password
-    match verify_password(&login_data.password, &user.password_hash) {
-        Ok(true) => {
-            let token = match create_jwt(&user.id.to_string(), &user.username) {
-                Ok(t) => t,
-                Err(e) => {
-                    return HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
-                        format!("Failed to create token: {}", e)
-                    ));
-                }
-            };
-            
-            HttpResponse::Ok().json(ApiResponse::success(AuthResponse {
-                token,
-                user: user.into(),
-            }))
-        }
-        Ok(false) => {
-            HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
-                "Invalid credentials".to_string()
-            ))
-        }
-        Err(e) => {
-            HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
-                format!("Failed to verify password: {}", e)
-            ))
-        }
+    if !verify_password(&login_data.password, &user.password_hash)? {
+        return Err(AppError::Unauthorized("Invalid credentials".to_string()));
     }
+    
+    let tokens = start_session(&pool, &jwt_keys, user.id, &user.username).await?;
+    
+    Ok(HttpResponse::Ok().json(ApiResponse::success(AuthResponse {
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
+        user: user.into(),
+    })))
 }
 
-// Helper function to extract user from JWT
-fn get_user_from_request(req: &HttpRequest) -> Result<Claims, String> {
-    let auth_header = req
-        .headers()
-        .get("Authorization")
-        .and_then(|h| h.to_str().ok())
-        .ok_or("Missing authorization header")?;
+// Exchange a refresh token for a new access and refresh token pair
+pub async fn refresh_token(
+    pool: web::Data<PgPool>,
+    jwt_keys: web::Data<JwtKeys>,
+    refresh_data: web::Json<RefreshRequest>,
+) -> Result<HttpResponse, AppError> {
+    refresh_data.validate()?;
+    
+    let invalid = || AppError::Unauthorized("Invalid or expired refresh token".to_string());
+    let token = database::get_refresh_token(&pool, &hash_refresh_token(&refresh_data.refresh_token))
+        .await?
+        .ok_or_else(invalid)?;
     
-    let token = extract_token_from_header(auth_header)
-        .ok_or("Invalid authorization header format")?;
+    if token.revoked_at.is_some() || token.expires_at <= Utc::now() {
+        return Err(invalid());
+    }
+    
+    // A used token coming back means it was copied; end the whole session
+    if token.used_at.is_some() || !database::use_refresh_token(&pool, token.id).await? {
+        log::warn!("Refresh token reuse detected for session {}", token.session_id);
+        database::revoke_session(&pool, token.session_id).await?;
+        return Err(invalid());
+    }
     
-    decode_jwt(token).map_err(|e| format!("Invalid token: {}", e))
+    let tokens = issue_tokens(&pool, &jwt_keys, token.user_id, &token.username, token.session_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(tokens)))
+}
+
+// Log out the current session
+pub async fn logout(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    database::revoke_session(&pool, user.session_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success("Logged out")))
+}
+
+// Log out every session of the current user
+pub async fn logout_all(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let revoked = database::revoke_user_sessions(&pool, user.id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(format!("Logged out of {} sessions", revoked))))
+}
+
+// Helper function to open a session and issue its first tokens
+async fn start_session(
+    pool: &PgPool,
+    jwt_keys: &JwtKeys,
+    user_id: Uuid,
+    username: &str,
+) -> Result<TokenResponse, AppError> {
+    let session_id = Uuid::new_v4();
+    database::create_session(pool, session_id, user_id).await?;
+    issue_tokens(pool, jwt_keys, user_id, username, session_id).await
+}
+
+// Helper function to issue an access token and a fresh refresh token
+async fn issue_tokens(
+    pool: &PgPool,
+    jwt_keys: &JwtKeys,
+    user_id: Uuid,
+    username: &str,
+    session_id: Uuid,
+) -> Result<TokenResponse, AppError> {
+    let (refresh_token, refresh_hash) = generate_refresh_token();
+    let expires_at = Utc::now() + jwt_keys.refresh_ttl();
+    database::create_refresh_token(pool, session_id, &refresh_hash, expires_at).await?;
+    
+    let token = jwt_keys.create_jwt(&user_id.to_string(), username, &session_id.to_string())?;
+    Ok(TokenResponse { token, refresh_token })
+}
+
+// Public keys for verifying access tokens
+pub async fn jwks(jwt_keys: web::Data<JwtKeys>) -> HttpResponse {
+    HttpResponse::Ok().json(jwt_keys.jwks())
+}
+
+// Upload a source image as multipart/form-data
+pub async fn upload_image(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    limits: web::Data<ImageLimits>,
+    payload: Multipart,
+) -> Result<HttpResponse, AppError> {
+    let upload_id = Uuid::new_v4();
+    let stored = uploads::save_multipart_image(payload, upload_id, limits.get_ref().clone()).await?;
+    
+    let image_url = format!("/{}", stored.path);
+    let format = uploads::extension_for(stored.format).unwrap_or("img");
+    let upload = database::create_upload(
+        &pool,
+        upload_id,
+        user.id,
+        &image_url,
+        format,
+        stored.width as i32,
+        stored.height as i32,
+        stored.size_bytes as i64,
+    ).await;
+    
+    match upload {
+        Ok(upload) => Ok(HttpResponse::Created().json(ApiResponse::success(upload))),
+        Err(e) => {
+            let _ = fs::remove_file(&stored.path);
+            Err(e.into())
+        }
+    }
 }
 
 // Create meme
 pub async fn create_meme(
-    req: HttpRequest,
+    user: AuthUser,
     pool: web::Data<PgPool>,
+    renderer: web::Data<dyn MemeRenderer>,
+    limits: web::Data<ImageLimits>,
     meme_data: web::Json<CreateMemeRequest>,
-) -> HttpResponse {
-    // Authenticate user
-    let claims = match get_user_from_request(&req) {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()>::error(e));
-        }
+) -> Result<HttpResponse, AppError> {
+    // Validate input
+    meme_data.validate()?;
+    
+    // Resolve the source image: an uploaded image wins over a named template
+    let meme_id = Uuid::new_v4();
+    let mut template_id = None;
+    let mut layout = TextBox::classic_layout();
+    let input_path = if let Some(upload_id) = meme_data.upload_id {
+        let upload = database::get_upload(&pool, upload_id, user.id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Upload not found".to_string()))?;
+        
+        uploads::stored_path(&upload.image_url, uploads::ORIGINALS_DIR)
+            .ok_or_else(|| AppError::Internal(format!("Unexpected upload path: {}", upload.image_url)))?
+    } else if let Some(image_data) = &meme_data.image_data {
+        let (bytes, format) = uploads::decode_base64_image(image_data, &limits)?;
+        uploads::save_original(meme_id, &bytes, format)?
+    } else if let Some(template_name) = &meme_data.template_name {
+        let template = database::get_template_by_name(&pool, template_name)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Template not found".to_string()))?;
+        
+        template_id = Some(template.id);
+        layout = template.text_boxes();
+        uploads::stored_path(&template.image_url, uploads::TEMPLATES_DIR)
+            .ok_or_else(|| AppError::Internal(format!("Unexpected template path: {}", template.image_url)))?
+    } else {
+        uploads::DEFAULT_TEMPLATE_PATH.to_string()
     };
     
-    // Validate input
-    if let Err(errors) = meme_data.validate() {
-        return HttpResponse::BadRequest().json(ApiResponse::<()>::error(
-            format!("Validation error: {:?}", errors)
-        ));
+    let captions = meme_data.captions();
+    let texts = std::iter::once(meme_data.title.as_str())
+        .chain(captions.values().map(String::as_str))
+        .chain(meme_data.overlays.iter().map(|o| o.text.as_str()));
+    let tags = tags::collect_tags(&meme_data.tags, texts);
+    let output_filename = format!("{}.jpg", meme_id);
+    let source_url = format!("/{}", input_path);
+    let saved = async {
+        let image_url = render_meme(
+            &renderer,
+            input_path,
+            &layout,
+            &captions,
+            meme_data.overlays.clone(),
+            &output_filename,
+        ).await?;
+        
+        // Save to database, keeping the source and layout so the meme can be re-rendered
+        database::create_meme(&pool, meme_id, user.id, &meme_data, &captions, &tags, &image_url, &source_url, &layout).await?;
+        Ok::<_, AppError>(())
+    }
+    .await;
+    if saved.is_err() {
+        // Nothing refers to the render or an inline original yet
+        let image_url = format!("/{}/{}", uploads::MEMES_DIR, output_filename);
+        uploads::remove_meme_files(meme_id, &image_url, Some(&source_url));
     }
+    saved?;
     
-    let user_id = match Uuid::parse_str(&claims.sub) {
-        Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(ApiResponse::<()>::error(
-                "Invalid user ID".to_string()
-            ));
+    if let Some(template_id) = template_id {
+        if let Err(e) = database::increment_template_usage(&pool, template_id).await {
+            log::warn!("Failed to update usage count for template {}: {}", template_id, e);
         }
-    };
+    }
     
-    // Generate file paths
-    let meme_id = Uuid::new_v4();
-    let input_path = format!("uploads/templates/default.jpg");
-    let output_filename = format!("{}.jpg", meme_id);
-    let output_path = format!("uploads/memes/{}", output_filename);
+    let meme = database::get_meme_by_id(&pool, meme_id, Some(user.id))
+        .await?
+        .ok_or_else(|| AppError::Internal(format!("Meme {} vanished after insert", meme_id)))?;
+    
+    let created = Event::MemeCreated { meme_id, author_id: user.id, username: user.username.clone() };
+    realtime::publish(&pool, &created).await;
+    Ok(HttpResponse::Created().json(ApiResponse::success(meme)))
+}
+
+// Helper function to fill a layout and render it to uploads/memes/{output_filename}
+async fn render_meme(
+    renderer: &web::Data<dyn MemeRenderer>,
+    input_path: String,
+    layout: &[TextBox],
+    captions: &std::collections::HashMap<String, String>,
+    overlays: Vec<TextOverlay>,
+    output_filename: &str,
+) -> Result<String, AppError> {
+    // Fill the layout's boxes by name
+    let filled = renderer::fill_layout(layout, captions).map_err(AppError::BadRequest)?;
     
     // Ensure directories exist
-    fs::create_dir_all("uploads/memes").ok();
+    fs::create_dir_all(uploads::MEMES_DIR).ok();
+    let output_path = format!("{}/{}", uploads::MEMES_DIR, output_filename);
+    
+    // Rendering is CPU-bound, so keep it off the async workers
+    let renderer = renderer.clone().into_inner();
+    web::block(move || {
+        renderer.render(&input_path, &filled, &overlays, &output_path)
+    })
+    .await??;
+    
+    Ok(format!("/{}/{}", uploads::MEMES_DIR, output_filename))
+}
+
+// Helper function to load a meme and check the user may change it
+async fn get_owned_meme(pool: &PgPool, meme_id: Uuid, user: &AuthUser) -> Result<Meme, AppError> {
+    let meme = database::get_meme_row(pool, meme_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Meme not found".to_string()))?;
     
-    // Process meme using C++ library
-    let processor = MemeProcessor::new();
-    let top_text = meme_data.top_text.as_deref().unwrap_or("");
-    let bottom_text = meme_data.bottom_text.as_deref().unwrap_or("");
+    if meme.user_id != user.id && !user.has_role("admin") {
+        return Err(AppError::Forbidden("You can only modify your own memes".to_string()));
+    }
+    Ok(meme)
+}
+
+// Edit a meme's title and captions, re-rendering the image if captions change
+pub async fn update_meme(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    renderer: web::Data<dyn MemeRenderer>,
+    meme_id: web::Path<Uuid>,
+    meme_data: web::Json<UpdateMemeRequest>,
+) -> Result<HttpResponse, AppError> {
+    meme_data.validate()?;
+    
+    let meme = get_owned_meme(&pool, *meme_id, &user).await?;
+    let title = meme_data.title.as_deref().unwrap_or(&meme.title);
+    let current = meme.captions.as_ref().map(|c| c.0.clone()).unwrap_or_default();
+    
+    let (captions, image_url) = if meme_data.changes_captions() {
+        let source_url = meme.source_url.as_deref()
+            .ok_or_else(|| AppError::Conflict("This meme was created before editing was supported".to_string()))?;
+        let input_path = uploads::stored_path(source_url, uploads::ORIGINALS_DIR)
+            .or_else(|| uploads::stored_path(source_url, uploads::TEMPLATES_DIR))
+            .ok_or_else(|| AppError::Internal(format!("Unexpected source path: {}", source_url)))?;
+        
+        let layout = meme.layout.as_ref().map(|l| l.0.clone()).unwrap_or_else(TextBox::classic_layout);
+        let overlays = meme.overlays.as_ref().map(|o| o.0.clone()).unwrap_or_default();
+        let captions = meme_data.apply_captions(current);
+        
+        // A new file name so clients don't keep showing a cached image
+        let output_filename = format!("{}-{}.jpg", meme.id, Uuid::new_v4().simple());
+        let image_url = render_meme(&renderer, input_path, &layout, &captions, overlays, &output_filename).await?;
+        (captions, image_url)
+    } else {
+        (current, meme.image_url.clone())
+    };
+    
+    database::update_meme(&pool, meme.id, title, &captions, &image_url)
+        .await
+        .inspect_err(|_| {
+            if image_url != meme.image_url {
+                uploads::remove_meme_image(&image_url);
+            }
+        })?;
+    
+    // Re-read hashtags whenever the text or the tags change
+    if meme_data.tags.is_some() || meme_data.title.is_some() || meme_data.changes_captions() {
+        let explicit = match &meme_data.tags {
+            Some(tags) => tags.clone(),
+            None => database::get_explicit_meme_tags(&pool, meme.id).await?,
+        };
+        let overlays = meme.overlays.as_ref().map(|o| o.0.as_slice()).unwrap_or_default();
+        let texts = std::iter::once(title)
+            .chain(captions.values().map(String::as_str))
+            .chain(overlays.iter().map(|o| o.text.as_str()));
+        database::set_meme_tags(&pool, meme.id, &tags::collect_tags(&explicit, texts)).await?;
+    }
+    
+    if image_url != meme.image_url {
+        uploads::remove_meme_image(&meme.image_url);
+    }
+    
+    let meme = database::get_meme_by_id(&pool, meme.id, Some(user.id))
+        .await?
+        .ok_or_else(|| AppError::NotFound("Meme not found".to_string()))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(meme)))
+}
+
+// Move a meme to the trash; it can be restored until it is purged
+pub async fn delete_meme(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    meme_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let meme = get_owned_meme(&pool, *meme_id, &user).await?;
+    
+    database::delete_meme(&pool, meme.id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success("Meme moved to trash")))
+}
+
+// List the current user's trashed memes with their purge dates
+pub async fn get_trash(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    trash: web::Data<TrashConfig>,
+) -> Result<HttpResponse, AppError> {
+    let mut memes = database::get_trashed_memes(&pool, user.id).await?;
+    for meme in &mut memes {
+        meme.purge_at = meme.deleted_at.map(|deleted_at| deleted_at + trash.retention);
+    }
     
-    if let Err(e) = processor.create_classic_meme(&input_path, top_text, bottom_text, &output_path) {
-        return HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
-            format!("Failed to process meme: {}", e)
-        ));
+    Ok(HttpResponse::Ok().json(ApiResponse::success(memes)))
+}
+
+// Take a meme back out of the trash
+pub async fn restore_meme(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    trash: web::Data<TrashConfig>,
+    meme_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let meme = database::get_trashed_meme_row(&pool, *meme_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Meme not found in trash".to_string()))?;
+    if meme.user_id != user.id && !user.has_role("admin") {
+        return Err(AppError::Forbidden("You can only restore your own memes".to_string()));
     }
     
-    // Save to database
-    let image_url = format!("/uploads/memes/{}", output_filename);
-    match database::create_meme(&pool, user_id, &meme_data, &image_url).await {
-        Ok(meme) => HttpResponse::Created().json(ApiResponse::success(meme)),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
-            format!("Failed to save meme: {}", e)
-        )),
+    if !database::restore_meme(&pool, meme.id, trash.retention).await? {
+        return Err(AppError::Conflict("The restore window for this meme has passed".to_string()));
     }
+    
+    let meme = database::get_meme_by_id(&pool, meme.id, Some(user.id))
+        .await?
+        .ok_or_else(|| AppError::NotFound("Meme not found".to_string()))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(meme)))
 }
 
-// Get all memes
+// Get a page of the feed
 pub async fn get_memes(
+    viewer: OptionalAuthUser,
     pool: web::Data<PgPool>,
     query: web::Query<std::collections::HashMap<String, String>>,
-) -> HttpResponse {
-    let limit: i64 = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(20);
-    let offset: i64 = query.get("offset").and_then(|o| o.parse().ok()).unwrap_or(0);
-    
-    match database::get_memes(&pool, limit, offset).await {
-        Ok(memes) => HttpResponse::Ok().json(ApiResponse::success(memes)),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
-            format!("Failed to fetch memes: {}", e)
-        )),
-    }
+) -> Result<HttpResponse, AppError> {
+    let page = PageRequest::from_query(&query)?;
+    
+    let mut memes = database::get_memes(&pool, viewer.0.map(|u| u.id), &MemeFilter::default(), &page).await?;
+    let next_cursor = page.finish(&mut memes);
+    Ok(HttpResponse::Ok().json(ApiResponse::page(memes, next_cursor)))
 }
 
 // Get meme by ID
 pub async fn get_meme(
+    viewer: OptionalAuthUser,
     pool: web::Data<PgPool>,
     meme_id: web::Path<Uuid>,
-) -> HttpResponse {
-    match database::get_meme_by_id(&pool, *meme_id).await {
-        Ok(Some(meme)) => {
-            // Increment view count
-            let _ = database::increment_meme_views(&pool, *meme_id).await;
-            HttpResponse::Ok().json(ApiResponse::success(meme))
-        }
-        Ok(None) => HttpResponse::NotFound().json(ApiResponse::<()>::error(
-            "Meme not found".to_string()
-        )),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
-            format!("Database error: {}", e)
-        )),
+) -> Result<HttpResponse, AppError> {
+    let meme = database::get_meme_by_id(&pool, *meme_id, viewer.0.map(|u| u.id))
+        .await?
+        .ok_or_else(|| AppError::NotFound("Meme not found".to_string()))?;
+    
+    // Increment view count
+    if let Ok(views) = database::increment_meme_views(&pool, *meme_id).await {
+        realtime::publish(&pool, &Event::MemeViews { meme_id: *meme_id, views }).await;
     }
+    Ok(HttpResponse::Ok().json(ApiResponse::success(meme)))
 }
 
-// Get user's memes
+// Get a page of the user's memes
 pub async fn get_user_memes(
-    req: HttpRequest,
+    user: AuthUser,
     pool: web::Data<PgPool>,
-) -> HttpResponse {
-    let claims = match get_user_from_request(&req) {
-        Ok(c) => c,
-        Err(e) => {
-            return HttpResponse::Unauthorized().json(ApiResponse::<()>::error(e));
-        }
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, AppError> {
+    let page = PageRequest::from_query(&query)?;
+    
+    let filter = MemeFilter { author_id: Some(user.id), ..MemeFilter::default() };
+    let mut memes = database::get_memes(&pool, Some(user.id), &filter, &page).await?;
+    let next_cursor = page.finish(&mut memes);
+    Ok(HttpResponse::Ok().json(ApiResponse::page(memes, next_cursor)))
+}
+
+// Full-text search over titles, captions and template names
+pub async fn search(
+    viewer: OptionalAuthUser,
+    pool: web::Data<PgPool>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, AppError> {
+    let search = SearchRequest::from_query(&query)?;
+    
+    let mut results = database::search_memes(&pool, viewer.0.map(|u| u.id), &search).await?;
+    let next_cursor = search.finish(&mut results);
+    Ok(HttpResponse::Ok().json(ApiResponse::page(results, next_cursor)))
+}
+
+// Get a page of memes with a tag
+pub async fn get_tag_memes(
+    viewer: OptionalAuthUser,
+    pool: web::Data<PgPool>,
+    tag: web::Path<String>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, AppError> {
+    let tag = tags::normalize_tag(&tag).ok_or_else(|| AppError::NotFound("Tag not found".to_string()))?;
+    let page = PageRequest::from_query(&query)?;
+    
+    let filter = MemeFilter { tag: Some(tag), ..MemeFilter::default() };
+    let mut memes = database::get_memes(&pool, viewer.0.map(|u| u.id), &filter, &page).await?;
+    let next_cursor = page.finish(&mut memes);
+    Ok(HttpResponse::Ok().json(ApiResponse::page(memes, next_cursor)))
+}
+
+// Autocomplete tags by prefix, most used first
+pub async fn get_tags(
+    pool: web::Data<PgPool>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, AppError> {
+    let limit: i64 = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(10).clamp(1, 50);
+    let prefix = match query.get("q").map(|q| q.trim()).filter(|q| !q.is_empty() && *q != "#") {
+        Some(q) => match tags::normalize_tag(q) {
+            Some(prefix) => prefix,
+            // Nothing can start with characters a tag can't contain
+            None => return Ok(HttpResponse::Ok().json(ApiResponse::success(Vec::<TagCount>::new()))),
+        },
+        None => String::new(),
     };
     
-    let user_id = match Uuid::parse_str(&claims.sub) {
-        Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(ApiResponse::<()>::error(
-                "Invalid user ID".to_string()
-            ));
-        }
+    let tags = database::search_tags(&pool, &prefix, limit).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(tags)))
+}
+
+// Tags used on the most new memes in a window
+pub async fn get_trending_tags(
+    pool: web::Data<PgPool>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, AppError> {
+    let limit: i64 = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(10).clamp(1, 50);
+    let window = match query.get("window") {
+        Some(window) => MemeWindow::parse(window)?,
+        None => MemeWindow::Week,
     };
     
-    match database::get_user_memes(&pool, user_id).await {
-        Ok(memes) => HttpResponse::Ok().json(ApiResponse::success(memes)),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
-            format!("Failed to fetch memes: {}", e)
-        )),
+    let tags = database::trending_tags(&pool, window.since(Utc::now()), limit).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(tags)))
+}
+
+// Get a user's public profile
+pub async fn get_user_profile(
+    viewer: OptionalAuthUser,
+    pool: web::Data<PgPool>,
+    username: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let profile = database::get_user_profile(&pool, &username, viewer.0.map(|u| u.id))
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    
+    Ok(HttpResponse::Ok().json(ApiResponse::success(profile)))
+}
+
+// Get a page of a user's memes
+pub async fn get_profile_memes(
+    viewer: OptionalAuthUser,
+    pool: web::Data<PgPool>,
+    username: web::Path<String>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, AppError> {
+    let page = PageRequest::from_query(&query)?;
+    let author = database::get_user_by_username(&pool, &username)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    
+    let filter = MemeFilter { author_id: Some(author.id), ..MemeFilter::default() };
+    let mut memes = database::get_memes(&pool, viewer.0.map(|u| u.id), &filter, &page).await?;
+    let next_cursor = page.finish(&mut memes);
+    Ok(HttpResponse::Ok().json(ApiResponse::page(memes, next_cursor)))
+}
+
+// Edit the current user's profile
+pub async fn update_profile(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    profile_data: web::Json<UpdateProfileRequest>,
+) -> Result<HttpResponse, AppError> {
+    profile_data.validate()?;
+    
+    database::update_user_profile(&pool, user.id, &profile_data).await?;
+    
+    let profile = database::get_user_profile(&pool, &user.username, Some(user.id))
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(profile)))
+}
+
+// Follow a user; following twice counts once
+pub async fn follow_user(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    username: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let followee = database::get_user_by_username(&pool, &username)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    if followee.id == user.id {
+        return Err(AppError::BadRequest("You can't follow yourself".to_string()));
+    }
+    
+    let (followers, added) = database::follow_user(&pool, user.id, followee.id).await?;
+    if added {
+        let changed = Event::FollowChanged { follower_id: user.id, followee_id: followee.id, following: true };
+        realtime::publish(&pool, &changed).await;
+        notifications::notify(&pool, followee.id, user.id, NotificationKind::Follow, None).await;
     }
+    Ok(HttpResponse::Ok().json(ApiResponse::success(FollowResponse { following: true, followers })))
+}
+
+// Stop following a user
+pub async fn unfollow_user(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    username: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let followee = database::get_user_by_username(&pool, &username)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    
+    let (followers, removed) = database::unfollow_user(&pool, user.id, followee.id).await?;
+    if removed {
+        let changed = Event::FollowChanged { follower_id: user.id, followee_id: followee.id, following: false };
+        realtime::publish(&pool, &changed).await;
+    }
+    Ok(HttpResponse::Ok().json(ApiResponse::success(FollowResponse { following: false, followers })))
+}
+
+// List who follows a user
+pub async fn get_followers(
+    pool: web::Data<PgPool>,
+    username: web::Path<String>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, AppError> {
+    list_follows(&pool, &username, true, &query).await
+}
+
+// List whom a user follows
+pub async fn get_following(
+    pool: web::Data<PgPool>,
+    username: web::Path<String>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, AppError> {
+    list_follows(&pool, &username, false, &query).await
+}
+
+// Helper for the follower and following lists
+async fn list_follows(
+    pool: &PgPool,
+    username: &str,
+    followers: bool,
+    query: &std::collections::HashMap<String, String>,
+) -> Result<HttpResponse, AppError> {
+    let limit = page_size(query, MAX_PAGE_SIZE)?;
+    let after: Option<FollowCursor> = query.get("cursor").map(|c| decode_cursor(c)).transpose()?;
+    let user = database::get_user_by_username(pool, username)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    
+    let mut users = database::get_follows(pool, user.id, followers, after.as_ref(), limit).await?;
+    let next_cursor = finish_page(&mut users, limit, |u| encode_cursor(&FollowCursor { followed_at: u.followed_at, id: u.id }));
+    
+    Ok(HttpResponse::Ok().json(ApiResponse::page(users, next_cursor)))
+}
+
+// Get a page of memes from the users the current user follows
+pub async fn get_following_feed(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, AppError> {
+    let page = PageRequest::from_query(&query)?;
+    
+    let filter = MemeFilter { followed_by: Some(user.id), ..MemeFilter::default() };
+    let mut memes = database::get_memes(&pool, Some(user.id), &filter, &page).await?;
+    let next_cursor = page.finish(&mut memes);
+    Ok(HttpResponse::Ok().json(ApiResponse::page(memes, next_cursor)))
 }
 
-// Like meme
+// Like meme; liking twice counts once
 pub async fn like_meme(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    meme_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let (likes, added) = database::like_meme(&pool, *meme_id, user.id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Meme not found".to_string()))?;
+    
+    realtime::publish(&pool, &Event::MemeLikes { meme_id: *meme_id, likes }).await;
+    if added {
+        if let Some(meme) = database::get_meme_row(&pool, *meme_id).await? {
+            notifications::notify(&pool, meme.user_id, user.id, NotificationKind::Like, Some(meme.id)).await;
+        }
+    }
+    
+    Ok(HttpResponse::Ok().json(ApiResponse::success(LikeResponse { liked: true, likes })))
+}
+
+// Remove the current user's like
+pub async fn unlike_meme(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    meme_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let likes = database::unlike_meme(&pool, *meme_id, user.id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Meme not found".to_string()))?;
+    
+    realtime::publish(&pool, &Event::MemeLikes { meme_id: *meme_id, likes }).await;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(LikeResponse { liked: false, likes })))
+}
+
+// List a meme's comments, or the replies to one of them
+pub async fn get_comments(
+    pool: web::Data<PgPool>,
+    meme_id: web::Path<Uuid>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, AppError> {
+    let limit: i64 = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(50).clamp(1, 100);
+    let offset: i64 = query.get("offset").and_then(|o| o.parse().ok()).unwrap_or(0).max(0);
+    let parent_id = match query.get("parent_id") {
+        Some(id) => Some(Uuid::parse_str(id).map_err(|_| AppError::BadRequest("Invalid parent_id".to_string()))?),
+        None => None,
+    };
+    
+    database::get_meme_row(&pool, *meme_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Meme not found".to_string()))?;
+    
+    let comments = database::get_comments(&pool, *meme_id, parent_id, limit, offset).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(comments)))
+}
+
+// Comment on a meme or reply to a comment
+pub async fn create_comment(
+    user: AuthUser,
     pool: web::Data<PgPool>,
     meme_id: web::Path<Uuid>,
-) -> HttpResponse {
-    match database::increment_meme_likes(&pool, *meme_id).await {
-        Ok(_) => HttpResponse::Ok().json(ApiResponse::success("Meme liked")),
-        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
-            format!("Failed to like meme: {}", e)
-        )),
+    comment_data: web::Json<CreateCommentRequest>,
+) -> Result<HttpResponse, AppError> {
+    comment_data.validate()?;
+    
+    let meme = database::get_meme_row(&pool, *meme_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Meme not found".to_string()))?;
+    
+    if let Some(parent_id) = comment_data.parent_id {
+        let parent = database::get_comment_by_id(&pool, parent_id).await?;
+        if parent.map(|p| p.meme_id) != Some(*meme_id) {
+            return Err(AppError::NotFound("Parent comment not found".to_string()));
+        }
+    }
+    
+    let comment_id = Uuid::new_v4();
+    database::create_comment(&pool, comment_id, *meme_id, user.id, comment_data.parent_id, &comment_data.content).await?;
+    notifications::notify(&pool, meme.user_id, user.id, NotificationKind::Comment, Some(meme.id)).await;
+    
+    let comment = database::get_comment_by_id(&pool, comment_id)
+        .await?
+        .ok_or_else(|| AppError::Internal(format!("Comment {} vanished after insert", comment_id)))?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(comment)))
+}
+
+// Edit your own comment
+pub async fn update_comment(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    comment_id: web::Path<Uuid>,
+    comment_data: web::Json<UpdateCommentRequest>,
+) -> Result<HttpResponse, AppError> {
+    comment_data.validate()?;
+    
+    let comment = database::get_comment_by_id(&pool, *comment_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Comment not found".to_string()))?;
+    
+    if comment.user_id != user.id {
+        return Err(AppError::Forbidden("You can only edit your own comments".to_string()));
+    }
+    
+    database::update_comment(&pool, *comment_id, &comment_data.content).await?;
+    
+    let comment = database::get_comment_by_id(&pool, *comment_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Comment not found".to_string()))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(comment)))
+}
+
+// Delete your own comment and its replies; admins can delete any comment
+pub async fn delete_comment(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    comment_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let comment = database::get_comment_by_id(&pool, *comment_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Comment not found".to_string()))?;
+    
+    if comment.user_id != user.id && !user.has_role("admin") {
+        return Err(AppError::Forbidden("You can only delete your own comments".to_string()));
+    }
+    
+    database::delete_comment(&pool, *comment_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success("Comment deleted")))
+}
+
+// List the current user's notifications with their unread count
+pub async fn get_notifications(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, AppError> {
+    let limit = page_size(&query, MAX_PAGE_SIZE)?;
+    let after: Option<NotificationCursor> = query.get("cursor").map(|c| decode_cursor(c)).transpose()?;
+    let unread_only = query.get("unread").is_some_and(|v| v == "true");
+    
+    let mut notifications = database::get_notifications(&pool, user.id, unread_only, after.as_ref(), limit).await?;
+    let next_cursor = finish_page(&mut notifications, limit, |n| {
+        encode_cursor(&NotificationCursor { updated_at: n.updated_at, id: n.id })
+    });
+    for notification in &mut notifications {
+        notification.message = notifications::describe(
+            &notification.kind,
+            &notification.actor_username,
+            notification.actor_count,
+            notification.meme_title.as_deref(),
+        );
+    }
+    
+    let unread_count = database::count_unread_notifications(&pool, user.id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::page(NotificationList { notifications, unread_count }, next_cursor)))
+}
+
+// Mark some or all notifications read
+pub async fn mark_notifications_read(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    read_data: web::Json<MarkReadRequest>,
+) -> Result<HttpResponse, AppError> {
+    read_data.validate()?;
+    
+    database::mark_notifications_read(&pool, user.id, read_data.ids.as_deref()).await?;
+    
+    // Other open tabs update their badge too
+    let unread_count = database::count_unread_notifications(&pool, user.id).await?;
+    realtime::publish(&pool, &Event::Notification { user_id: user.id, unread_count }).await;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(UnreadCount { unread_count })))
+}
+
+// Get the current user's notification preferences
+pub async fn get_notification_preferences(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let preferences = database::get_notification_preferences(&pool, user.id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(preferences)))
+}
+
+// Turn notification kinds on or off
+pub async fn update_notification_preferences(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    preferences_data: web::Json<UpdateNotificationPreferences>,
+) -> Result<HttpResponse, AppError> {
+    let preferences = database::update_notification_preferences(&pool, user.id, &preferences_data).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(preferences)))
+}
+
+// Stream live updates as server-sent events: like and view counts for
+// everyone, plus new memes from followed users and the unread notification
+// count for signed-in users
+pub async fn events(
+    viewer: OptionalAuthUser,
+    pool: web::Data<PgPool>,
+    hub: web::Data<EventHub>,
+) -> Result<HttpResponse, AppError> {
+    let mut subscriber = Subscriber::default();
+    let mut session = None;
+    let mut initial = Vec::new();
+    if let Some(user) = viewer.0 {
+        subscriber.user_id = Some(user.id);
+        session = user.token_expires_at.map(|expires_at| StreamSession { session_id: user.session_id, expires_at });
+        subscriber.following = database::get_followee_ids(&pool, user.id).await?.into_iter().collect();
+        
+        // Start with the current count so reconnecting clients catch up
+        let unread_count = database::count_unread_notifications(&pool, user.id).await?;
+        initial.push(Event::Notification { user_id: user.id, unread_count });
     }
+    
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // Stop nginx from buffering the stream
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(realtime::event_stream(&hub, pool.get_ref().clone(), subscriber, session, initial)))
+}
+
+// List templates, most used first
+pub async fn get_templates(
+    pool: web::Data<PgPool>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> Result<HttpResponse, AppError> {
+    let limit: i64 = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(20).clamp(1, 100);
+    let offset: i64 = query.get("offset").and_then(|o| o.parse().ok()).unwrap_or(0).max(0);
+    let search = query.get("q").map(|q| q.trim()).filter(|q| !q.is_empty());
+    
+    let templates = database::get_templates(&pool, search, limit, offset).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(templates)))
+}
+
+// Get template by ID
+pub async fn get_template(
+    pool: web::Data<PgPool>,
+    template_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let template = database::get_template_by_id(&pool, *template_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Template not found".to_string()))?;
+    
+    Ok(HttpResponse::Ok().json(ApiResponse::success(template)))
+}
+
+// Upload a new template
+pub async fn create_template(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    limits: web::Data<ImageLimits>,
+    template_data: web::Json<CreateTemplateRequest>,
+) -> Result<HttpResponse, AppError> {
+    // Templates are shown to everyone, so only admins may add them
+    if !user.has_role("admin") {
+        return Err(AppError::Forbidden("Only admins can add templates".to_string()));
+    }
+    template_data.validate()?;
+    
+    if database::get_template_by_name(&pool, &template_data.name).await?.is_some() {
+        return Err(AppError::Conflict("Template name already exists".to_string()));
+    }
+    
+    let (bytes, format) = uploads::decode_base64_image(&template_data.image_data, &limits)?;
+    
+    // Store the base image under the template's ID
+    let template_id = Uuid::new_v4();
+    let extension = uploads::extension_for(format).unwrap_or("png");
+    let filename = format!("{}.{}", template_id, extension);
+    fs::create_dir_all(uploads::TEMPLATES_DIR).ok();
+    fs::write(format!("{}/{}", uploads::TEMPLATES_DIR, filename), &bytes)?;
+    
+    let image_url = format!("/uploads/templates/{}", filename);
+    let template = database::create_template(
+        &pool,
+        template_id,
+        &template_data.name,
+        template_data.description.as_deref(),
+        template_data.layout.as_deref(),
+        &image_url,
+    )
+    .await
+    .inspect_err(|_| uploads::remove_template_image(&image_url))?;
+    
+    Ok(HttpResponse::Created().json(ApiResponse::success(template)))
 }
@@ -1,4 +1,9 @@
-use std::ffi::{CString, c_void, c_char};
+use std::ffi::{CString, c_void, c_char, c_int};
+use std::fs;
+
+use crate::image_limits::ImageLimits;
+use crate::models::{TextAlign, TextBox, TextOverlay};
+use crate::renderer::{check_overlay_area, MemeRenderer, RenderError};
 
 #[link(name = "meme_processor")]
 extern "C" {
@@ -11,17 +16,110 @@ extern "C" {
         bottom_text: *const c_char,
         output_path: *const c_char,
     ) -> bool;
+    fn meme_processor_load_image(processor: *mut c_void, input_path: *const c_char) -> bool;
+    fn meme_processor_get_dimensions(processor: *mut c_void, width: *mut c_int, height: *mut c_int);
+    fn meme_processor_add_text(
+        processor: *mut c_void,
+        text: *const c_char,
+        x: c_int,
+        y: c_int,
+        font_size: c_int,
+        color: *const c_char,
+        stroke_color: *const c_char,
+        stroke_width: c_int,
+        align: *const c_char,
+    ) -> bool;
+    fn meme_processor_save_image(processor: *mut c_void, output_path: *const c_char) -> bool;
 }
 
 pub struct MemeProcessor {
     processor: *mut c_void,
+    limits: ImageLimits,
+}
+
+impl Default for MemeProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MemeProcessor {
     pub fn new() -> Self {
+        Self::with_limits(ImageLimits::default())
+    }
+    
+    /// A processor whose `render` refuses source images outside `limits`.
+    pub fn with_limits(limits: ImageLimits) -> Self {
         unsafe {
             MemeProcessor {
                 processor: meme_processor_new(),
+                limits,
+            }
+        }
+    }
+    
+    pub fn load_image(&self, input_path: &str) -> Result<(), String> {
+        let input_path_c = CString::new(input_path).map_err(|e| e.to_string())?;
+        
+        unsafe {
+            if meme_processor_load_image(self.processor, input_path_c.as_ptr()) {
+                Ok(())
+            } else {
+                Err("Failed to load image".to_string())
+            }
+        }
+    }
+    
+    pub fn dimensions(&self) -> (i32, i32) {
+        let mut width: c_int = 0;
+        let mut height: c_int = 0;
+        unsafe {
+            meme_processor_get_dimensions(self.processor, &mut width, &mut height);
+        }
+        (width, height)
+    }
+    
+    /// Adds an overlay, converting its fractional position to pixels for an
+    /// image of the given size.
+    pub fn add_text(&self, overlay: &TextOverlay, width: i32, height: i32) -> Result<(), String> {
+        let text_c = CString::new(overlay.text.as_str()).map_err(|e| e.to_string())?;
+        let color_c = CString::new(overlay.color.as_str()).map_err(|e| e.to_string())?;
+        let stroke_color_c = CString::new(overlay.stroke_color.as_str()).map_err(|e| e.to_string())?;
+        let align_c = CString::new(match overlay.align {
+            TextAlign::Left => "left",
+            TextAlign::Center => "center",
+            TextAlign::Right => "right",
+        }).map_err(|e| e.to_string())?;
+        
+        unsafe {
+            let success = meme_processor_add_text(
+                self.processor,
+                text_c.as_ptr(),
+                (overlay.x * width as f32).round() as c_int,
+                (overlay.y * height as f32).round() as c_int,
+                overlay.font_size.round() as c_int,
+                color_c.as_ptr(),
+                stroke_color_c.as_ptr(),
+                overlay.stroke_width.round() as c_int,
+                align_c.as_ptr(),
+            );
+            
+            if success {
+                Ok(())
+            } else {
+                Err("Failed to add text".to_string())
+            }
+        }
+    }
+    
+    pub fn save_image(&self, output_path: &str) -> Result<(), String> {
+        let output_path_c = CString::new(output_path).map_err(|e| e.to_string())?;
+        
+        unsafe {
+            if meme_processor_save_image(self.processor, output_path_c.as_ptr()) {
+                Ok(())
+            } else {
+                Err("Failed to save image".to_string())
             }
         }
     }
@@ -56,6 +154,50 @@ impl MemeProcessor {
     }
 }
 
+impl MemeRenderer for MemeProcessor {
+    fn render(
+        &self,
+        input_path: &str,
+        captions: &[(TextBox, String)],
+        overlays: &[TextOverlay],
+        output_path: &str,
+    ) -> Result<(), RenderError> {
+        check_overlay_area(overlays)?;
+        
+        // The C++ loader has no limits of its own, so check the header first
+        let bytes = fs::read(input_path)
+            .map_err(|e| RenderError::Failed(format!("Failed to load image: {}", e)))?;
+        self.limits.inspect(&bytes)?;
+        drop(bytes);
+        
+        // The C++ processor keeps the loaded image between calls, so each render
+        // gets its own instance rather than sharing `self` across threads
+        let processor = MemeProcessor::with_limits(self.limits.clone());
+        processor.load_image(input_path).map_err(RenderError::Failed)?;
+        let (width, height) = processor.dimensions();
+        
+        // Boxes are approximated by their centre; the C++ side has no fitting
+        for (text_box, text) in captions {
+            let overlay = TextOverlay {
+                text: text.to_uppercase(),
+                x: text_box.x + text_box.width / 2.0,
+                y: text_box.y + text_box.height / 2.0,
+                font_size: text_box.max_font_size.unwrap_or(height as f32 / 8.0),
+                color: "white".to_string(),
+                stroke_color: "black".to_string(),
+                stroke_width: 2.0,
+                align: TextAlign::Center,
+            };
+            processor.add_text(&overlay, width, height).map_err(RenderError::Failed)?;
+        }
+        for overlay in overlays {
+            processor.add_text(overlay, width, height).map_err(RenderError::Failed)?;
+        }
+        
+        processor.save_image(output_path).map_err(RenderError::Failed)
+    }
+}
+
 impl Drop for MemeProcessor {
     fn drop(&mut self) {
         unsafe {
@@ -77,4 +219,15 @@ mod tests {
         // Processor should be created successfully
         assert!(!processor.processor.is_null());
     }
+    
+    #[test]
+    fn test_render_applies_image_limits() {
+        let path = std::env::temp_dir().join(format!("{}.png", uuid::Uuid::new_v4()));
+        image::RgbImage::new(64, 64).save(&path).unwrap();
+        
+        let limits = ImageLimits { max_width: 32, ..ImageLimits::default() };
+        let result = MemeProcessor::with_limits(limits).render(path.to_str().unwrap(), &[], &[], "unused.jpg");
+        fs::remove_file(&path).ok();
+        assert!(matches!(result, Err(RenderError::Rejected(_))));
+    }
 }
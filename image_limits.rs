@@ -0,0 +1,271 @@
+use std::fmt;
+use std::io::Cursor;
+
+use image::io::{Limits, Reader as ImageReader};
+use image::{DynamicImage, ImageFormat};
+
+use crate::config;
+use crate::uploads::extension_for;
+
+/// Why an image was refused before or during decoding.
+#[derive(Debug)]
+pub enum ImageRejection {
+    /// Not one of the accepted formats.
+    Unsupported(String),
+    /// Exceeds a configured dimension, pixel, frame or memory limit.
+    TooLarge(String),
+    /// Truncated or otherwise undecodable data.
+    Malformed(String),
+}
+
+impl fmt::Display for ImageRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageRejection::Unsupported(msg)
+            | ImageRejection::TooLarge(msg)
+            | ImageRejection::Malformed(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+/// What the header says about an image, read without decoding pixel data.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageInfo {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+    pub frames: u32,
+}
+
+/// Limits applied to every user-supplied image.
+///
+/// Headers are checked first so a small file claiming enormous dimensions is
+/// refused without allocating; the decode itself then runs under
+/// `max_decode_bytes`.
+#[derive(Debug, Clone)]
+pub struct ImageLimits {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_pixels: u64,
+    pub max_frames: u32,
+    pub max_decode_bytes: u64,
+}
+
+impl Default for ImageLimits {
+    fn default() -> Self {
+        ImageLimits {
+            max_width: 8192,
+            max_height: 8192,
+            max_pixels: 25_000_000,
+            max_frames: 100,
+            max_decode_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+impl ImageLimits {
+    /// Reads `IMAGE_MAX_WIDTH`, `IMAGE_MAX_HEIGHT`, `IMAGE_MAX_PIXELS`,
+    /// `IMAGE_MAX_FRAMES` and `IMAGE_MAX_DECODE_BYTES`, keeping the default for
+    /// any that are unset or unparsable.
+    pub fn from_env() -> Self {
+        let defaults = ImageLimits::default();
+        ImageLimits {
+            max_width: config::read("IMAGE_MAX_WIDTH").unwrap_or(defaults.max_width),
+            max_height: config::read("IMAGE_MAX_HEIGHT").unwrap_or(defaults.max_height),
+            max_pixels: config::read("IMAGE_MAX_PIXELS").unwrap_or(defaults.max_pixels),
+            max_frames: config::read("IMAGE_MAX_FRAMES").unwrap_or(defaults.max_frames),
+            max_decode_bytes: config::read("IMAGE_MAX_DECODE_BYTES").unwrap_or(defaults.max_decode_bytes),
+        }
+    }
+
+    /// Checks format, dimensions and frame count from the header alone.
+    pub fn inspect(&self, bytes: &[u8]) -> Result<ImageInfo, ImageRejection> {
+        let format = image::guess_format(bytes)
+            .map_err(|_| ImageRejection::Unsupported("Unrecognized image format".to_string()))?;
+        if extension_for(format).is_none() {
+            return Err(ImageRejection::Unsupported(
+                "Only PNG, JPEG, GIF and WebP images are supported".to_string()
+            ));
+        }
+
+        let (width, height) = ImageReader::with_format(Cursor::new(bytes), format)
+            .into_dimensions()
+            .map_err(|e| ImageRejection::Malformed(format!("Invalid image: {}", e)))?;
+
+        if width == 0 || height == 0 {
+            return Err(ImageRejection::Malformed("Image has no pixels".to_string()));
+        }
+        if width > self.max_width || height > self.max_height {
+            return Err(ImageRejection::TooLarge(format!(
+                "Image dimensions must not exceed {}x{}",
+                self.max_width, self.max_height
+            )));
+        }
+        if width as u64 * height as u64 > self.max_pixels {
+            return Err(ImageRejection::TooLarge(format!(
+                "Image must not exceed {} pixels",
+                self.max_pixels
+            )));
+        }
+
+        let frames = count_frames(bytes, format)?;
+        if frames > self.max_frames {
+            return Err(ImageRejection::TooLarge(format!(
+                "Animated images must not exceed {} frames",
+                self.max_frames
+            )));
+        }
+
+        Ok(ImageInfo { format, width, height, frames })
+    }
+
+    /// Inspects the header, then decodes under the configured memory budget.
+    pub fn decode(&self, bytes: &[u8]) -> Result<DynamicImage, ImageRejection> {
+        let info = self.inspect(bytes)?;
+
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(self.max_width);
+        limits.max_image_height = Some(self.max_height);
+        limits.max_alloc = Some(self.max_decode_bytes);
+
+        let mut reader = ImageReader::with_format(Cursor::new(bytes), info.format);
+        reader.limits(limits);
+        reader.decode().map_err(|e| match e {
+            image::ImageError::Limits(_) => {
+                ImageRejection::TooLarge("Image exceeds the decoding memory budget".to_string())
+            }
+            other => ImageRejection::Malformed(format!("Invalid image: {}", other)),
+        })
+    }
+}
+
+/// Counts animation frames by walking the container structure, without
+/// decoding any pixel data.
+fn count_frames(bytes: &[u8], format: ImageFormat) -> Result<u32, ImageRejection> {
+    match format {
+        ImageFormat::Gif => count_gif_frames(bytes),
+        ImageFormat::Png => Ok(count_png_frames(bytes)),
+        ImageFormat::WebP => Ok(count_webp_frames(bytes)),
+        _ => Ok(1),
+    }
+}
+
+fn count_gif_frames(bytes: &[u8]) -> Result<u32, ImageRejection> {
+    let truncated = || ImageRejection::Malformed("Truncated GIF".to_string());
+
+    // Header, logical screen descriptor and optional global colour table
+    let flags = *bytes.get(10).ok_or_else(truncated)?;
+    let mut pos = 13;
+    if flags & 0x80 != 0 {
+        pos += 3 << ((flags & 0x07) + 1);
+    }
+
+    let skip_sub_blocks = |mut pos: usize| -> Result<usize, ImageRejection> {
+        loop {
+            let size = *bytes.get(pos).ok_or_else(truncated)? as usize;
+            pos += 1;
+            if size == 0 {
+                return Ok(pos);
+            }
+            pos += size;
+        }
+    };
+
+    let mut frames = 0;
+    loop {
+        match *bytes.get(pos).ok_or_else(truncated)? {
+            // Extension: label, then data sub-blocks
+            0x21 => pos = skip_sub_blocks(pos + 2)?,
+            // Image descriptor, optional local colour table, LZW code size, data
+            0x2C => {
+                frames += 1;
+                let local_flags = *bytes.get(pos + 9).ok_or_else(truncated)?;
+                pos += 10;
+                if local_flags & 0x80 != 0 {
+                    pos += 3 << ((local_flags & 0x07) + 1);
+                }
+                pos = skip_sub_blocks(pos + 1)?;
+            }
+            // Trailer
+            0x3B => return Ok(frames),
+            _ => return Err(ImageRejection::Malformed("Corrupt GIF block".to_string())),
+        }
+    }
+}
+
+fn count_png_frames(bytes: &[u8]) -> u32 {
+    // APNG declares its frame count in an acTL chunk ahead of the image data
+    let mut pos = 8;
+    while pos + 8 <= bytes.len() {
+        let length = u32::from_be_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let kind = &bytes[pos + 4..pos + 8];
+        if kind == b"acTL" {
+            return bytes
+                .get(pos + 8..pos + 12)
+                .map(|n| u32::from_be_bytes([n[0], n[1], n[2], n[3]]))
+                .unwrap_or(1);
+        }
+        if kind == b"IDAT" {
+            break;
+        }
+        pos = pos.saturating_add(12).saturating_add(length);
+    }
+    1
+}
+
+fn count_webp_frames(bytes: &[u8]) -> u32 {
+    let mut frames = 0;
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let size = u32::from_le_bytes([bytes[pos + 4], bytes[pos + 5], bytes[pos + 6], bytes[pos + 7]]) as usize;
+        if &bytes[pos..pos + 4] == b"ANMF" {
+            frames += 1;
+        }
+        // Chunks are padded to an even length
+        pos = pos.saturating_add(8).saturating_add(size + (size & 1));
+    }
+    frames.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifEncoder;
+    use image::{Frame, RgbaImage};
+
+    fn png_bytes(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_rejects_oversized_dimensions_before_decoding() {
+        let limits = ImageLimits { max_width: 10, ..ImageLimits::default() };
+        assert!(matches!(limits.inspect(&png_bytes(20, 5)), Err(ImageRejection::TooLarge(_))));
+        assert!(limits.decode(&png_bytes(10, 5)).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_truncated_image() {
+        let bytes = png_bytes(16, 16);
+        let limits = ImageLimits::default();
+        assert!(matches!(limits.decode(&bytes[..bytes.len() - 20]), Err(ImageRejection::Malformed(_))));
+    }
+
+    #[test]
+    fn test_counts_gif_frames() {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut bytes);
+            let frames = (0..3).map(|_| Frame::new(RgbaImage::new(4, 4)));
+            encoder.encode_frames(frames).unwrap();
+        }
+        assert_eq!(count_gif_frames(&bytes).unwrap(), 3);
+
+        let limits = ImageLimits { max_frames: 2, ..ImageLimits::default() };
+        assert!(matches!(limits.inspect(&bytes), Err(ImageRejection::TooLarge(_))));
+    }
+}
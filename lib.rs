@@ -2,4 +2,17 @@ pub mod models;
 pub mod database;
 pub mod auth;
 pub mod handlers;
+pub mod errors;
+pub mod config;
+pub mod image_limits;
+pub mod renderer;
+pub mod uploads;
+pub mod pagination;
+pub mod ranking;
+pub mod search;
+pub mod tags;
+pub mod notifications;
+pub mod trash;
+pub mod realtime;
+#[cfg(feature = "cpp-ffi")]
 pub mod image_ffi;
@@ -4,9 +4,18 @@ use actix_files::Files;
 use dotenv::dotenv;
 use std::env;
 
+use mememage_backend::auth::JwtKeys;
 use mememage_backend::database;
+use mememage_backend::image_limits::ImageLimits;
+use mememage_backend::ranking::{self, RankingConfig};
+use mememage_backend::realtime::{self, EventHub};
+use mememage_backend::renderer::renderer_from_env;
+use mememage_backend::trash::{self, TrashConfig};
 use mememage_backend::handlers::*;
 
+// A 10 MB image plus base64 overhead and the surrounding fields
+const JSON_BODY_LIMIT: usize = 14 * 1024 * 1024;
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Load environment variables
@@ -25,6 +34,29 @@ async fn main() -> std::io::Result<()> {
         .await
         .expect("Failed to create database pool");
     
+    let jwt_keys = web::Data::new(
+        JwtKeys::from_env().expect("Failed to load JWT keys")
+    );
+    
+    let image_limits = ImageLimits::from_env();
+    // A missing font is a configuration error, not a crash
+    let renderer = web::Data::from(renderer_from_env(&image_limits).map_err(|e| {
+        log::error!("Failed to initialize meme renderer: {}", e);
+        std::io::Error::other(e)
+    })?);
+    let image_limits = web::Data::new(image_limits);
+    
+    let trash_config = TrashConfig::from_env();
+    trash::spawn_purge_task(pool.clone(), trash_config.clone());
+    let trash_config = web::Data::new(trash_config);
+    
+    ranking::spawn_score_task(pool.clone(), RankingConfig::from_env());
+    
+    // Every instance relays events published through Postgres to its own clients
+    let event_hub = EventHub::new();
+    realtime::spawn_listener(database_url.clone(), event_hub.clone());
+    let event_hub = web::Data::new(event_hub);
+    
     log::info!("Starting MemEmage server at {}:{}", host, port);
     
     // Start HTTP server
@@ -37,6 +69,13 @@ async fn main() -> std::io::Result<()> {
         
         App::new()
             .app_data(web::Data::new(pool.clone()))
+            .app_data(jwt_keys.clone())
+            .app_data(renderer.clone())
+            .app_data(image_limits.clone())
+            .app_data(trash_config.clone())
+            .app_data(event_hub.clone())
+            // Base64 images arrive inside JSON bodies
+            .app_data(web::JsonConfig::default().limit(JSON_BODY_LIMIT))
             .wrap(middleware::Logger::default())
             .wrap(cors)
             // API routes
@@ -46,13 +85,56 @@ async fn main() -> std::io::Result<()> {
                     // Auth routes
                     .route("/auth/signup", web::post().to(signup))
                     .route("/auth/login", web::post().to(login))
+                    .route("/auth/refresh", web::post().to(refresh_token))
+                    .route("/auth/logout", web::post().to(logout))
+                    .route("/auth/logout-all", web::post().to(logout_all))
+                    // Upload routes
+                    .route("/uploads", web::post().to(upload_image))
                     // Meme routes
                     .route("/memes", web::post().to(create_meme))
                     .route("/memes", web::get().to(get_memes))
+                    // Registered before /memes/{id} so "trash" isn't parsed as an id
+                    .route("/memes/trash", web::get().to(get_trash))
                     .route("/memes/{id}", web::get().to(get_meme))
+                    .route("/memes/{id}", web::patch().to(update_meme))
+                    .route("/memes/{id}", web::delete().to(delete_meme))
+                    .route("/memes/{id}/restore", web::post().to(restore_meme))
                     .route("/memes/{id}/like", web::post().to(like_meme))
+                    .route("/memes/{id}/like", web::delete().to(unlike_meme))
                     .route("/memes/user/my-memes", web::get().to(get_user_memes))
+                    // Search
+                    .route("/search", web::get().to(search))
+                    // Comment routes
+                    .route("/memes/{id}/comments", web::get().to(get_comments))
+                    .route("/memes/{id}/comments", web::post().to(create_comment))
+                    .route("/comments/{id}", web::patch().to(update_comment))
+                    .route("/comments/{id}", web::delete().to(delete_comment))
+                    // User routes
+                    .route("/users/me", web::patch().to(update_profile))
+                    .route("/users/{username}", web::get().to(get_user_profile))
+                    .route("/users/{username}/memes", web::get().to(get_profile_memes))
+                    .route("/users/{username}/follow", web::post().to(follow_user))
+                    .route("/users/{username}/follow", web::delete().to(unfollow_user))
+                    .route("/users/{username}/followers", web::get().to(get_followers))
+                    .route("/users/{username}/following", web::get().to(get_following))
+                    .route("/feed/following", web::get().to(get_following_feed))
+                    // Notification routes
+                    .route("/notifications", web::get().to(get_notifications))
+                    .route("/notifications/read", web::post().to(mark_notifications_read))
+                    .route("/notifications/preferences", web::get().to(get_notification_preferences))
+                    .route("/notifications/preferences", web::patch().to(update_notification_preferences))
+                    // Live updates
+                    .route("/events", web::get().to(events))
+                    // Tag routes
+                    .route("/tags", web::get().to(get_tags))
+                    .route("/tags/trending", web::get().to(get_trending_tags))
+                    .route("/tags/{tag}/memes", web::get().to(get_tag_memes))
+                    // Template routes
+                    .route("/templates", web::get().to(get_templates))
+                    .route("/templates", web::post().to(create_template))
+                    .route("/templates/{id}", web::get().to(get_template))
             )
+            .route("/.well-known/jwks.json", web::get().to(jwks))
             // Static files
             .service(Files::new("/uploads", "./uploads"))
             // Frontend (if built)
@@ -121,17 +121,28 @@ bool MemeProcessor::addText(const TextOverlay& overlay) {
     for (size_t i = 0; i < lines.size(); ++i) {
         int current_y = start_y + i * line_height;
         
-        // Draw stroke (black outline)
-        for (int dx = -2; dx <= 2; ++dx) {
-            for (int dy = -2; dy <= 2; ++dy) {
+        // x is the anchor for the requested alignment
+        int line_width = static_cast<int>(lines[i].length()) * overlay.font_size / 2;
+        int line_x = x;
+        if (overlay.align == "center") {
+            line_x = x - line_width / 2;
+        } else if (overlay.align == "right") {
+            line_x = x - line_width;
+        }
+        
+        // Draw stroke (outline)
+        int stroke = overlay.stroke_width;
+        for (int dx = -stroke; dx <= stroke; ++dx) {
+            for (int dy = -stroke; dy <= stroke; ++dy) {
                 if (dx != 0 || dy != 0) {
-                    drawText(lines[i], x + dx, current_y + dy, overlay.font_size, "black");
+                    drawText(lines[i], line_x + dx, current_y + dy, overlay.font_size,
+                             overlay.stroke_color);
                 }
             }
         }
         
         // Draw main text
-        drawText(lines[i], x, current_y, overlay.font_size, overlay.color);
+        drawText(lines[i], line_x, current_y, overlay.font_size, overlay.color);
     }
     
     return true;
@@ -204,4 +215,44 @@ extern "C" {
         auto* proc = static_cast<mememage::MemeProcessor*>(processor);
         return proc->createClassicMeme(input_path, top_text, bottom_text, output_path);
     }
+    
+    bool meme_processor_load_image(void* processor, const char* input_path) {
+        auto* proc = static_cast<mememage::MemeProcessor*>(processor);
+        return proc->loadImage(input_path);
+    }
+    
+    void meme_processor_get_dimensions(void* processor, int* width, int* height) {
+        auto* proc = static_cast<mememage::MemeProcessor*>(processor);
+        auto dimensions = proc->getDimensions();
+        *width = dimensions.first;
+        *height = dimensions.second;
+    }
+    
+    bool meme_processor_add_text(void* processor,
+                                 const char* text,
+                                 int x,
+                                 int y,
+                                 int font_size,
+                                 const char* color,
+                                 const char* stroke_color,
+                                 int stroke_width,
+                                 const char* align) {
+        auto* proc = static_cast<mememage::MemeProcessor*>(processor);
+        mememage::TextOverlay overlay;
+        overlay.text = text;
+        overlay.x = x;
+        overlay.y = y;
+        overlay.font_size = font_size;
+        overlay.position = "custom";
+        overlay.color = color;
+        overlay.stroke_color = stroke_color;
+        overlay.stroke_width = stroke_width;
+        overlay.align = align;
+        return proc->addText(overlay);
+    }
+    
+    bool meme_processor_save_image(void* processor, const char* output_path) {
+        auto* proc = static_cast<mememage::MemeProcessor*>(processor);
+        return proc->saveImage(output_path);
+    }
 }
@@ -13,6 +13,9 @@ struct TextOverlay {
     int font_size;
     std::string position; // "top", "bottom", "custom"
     std::string color;
+    std::string stroke_color = "black";
+    int stroke_width = 2;
+    std::string align = "center"; // "left", "center", "right"
 };
 
 class MemeProcessor {
@@ -1,8 +1,10 @@
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use sqlx::types::Json;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
-use validator::Validate;
+use validator::{Validate, ValidationError};
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
 pub struct User {
@@ -10,6 +12,10 @@ pub struct User {
     pub username: String,
     pub email: String,
     pub password_hash: String,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub avatar_url: Option<String>,
+    pub roles: Vec<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -35,12 +41,96 @@ pub struct UserLogin {
     pub password: String,
 }
 
+/// A user's public profile, with totals over their live memes.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct UserProfile {
+    pub username: String,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub avatar_url: Option<String>,
+    pub joined_at: DateTime<Utc>,
+    pub meme_count: i64,
+    pub total_likes: i64,
+    pub total_views: i64,
+    pub follower_count: i64,
+    pub following_count: i64,
+    // Whether the requesting user follows this user
+    pub followed_by_me: bool,
+}
+
+/// An entry in a follower or following list.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct FollowUser {
+    // Kept for building cursors
+    #[serde(skip)]
+    pub id: Uuid,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub followed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FollowResponse {
+    pub following: bool,
+    pub followers: i64,
+}
+
+/// Profile changes; omitted fields are kept and an empty string clears one.
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateProfileRequest {
+    #[validate(length(max = 50))]
+    pub display_name: Option<String>,
+    
+    #[validate(length(max = 500))]
+    pub bio: Option<String>,
+    
+    #[validate(length(max = 500), custom = "validate_avatar_url")]
+    pub avatar_url: Option<String>,
+}
+
+// Avatars are either our own uploads or absolute http(s) URLs
+fn validate_avatar_url(url: &str) -> Result<(), ValidationError> {
+    let local = url.starts_with("/uploads/") && !url.contains("..");
+    let remote = (url.starts_with("https://") || url.starts_with("http://")) && validator::validate_url(url);
+    if url.is_empty() || local || remote {
+        Ok(())
+    } else {
+        Err(ValidationError::new("invalid_avatar_url"))
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthResponse {
     pub token: String,
+    pub refresh_token: String,
     pub user: UserInfo,
 }
 
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct RefreshRequest {
+    #[validate(length(min = 1, max = 200))]
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub token: String,
+    pub refresh_token: String,
+}
+
+/// A refresh token joined with its session and owner.
+#[derive(Debug, FromRow)]
+pub struct RefreshToken {
+    pub id: Uuid,
+    pub session_id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UserInfo {
     pub id: Uuid,
@@ -67,9 +157,23 @@ pub struct Meme {
     pub top_text: Option<String>,
     pub bottom_text: Option<String>,
     pub template_name: Option<String>,
+    // Text per layout box, keyed by box name
+    pub captions: Option<Json<HashMap<String, String>>>,
+    pub overlays: Option<Json<Vec<TextOverlay>>>,
+    pub source_url: Option<String>,
+    pub layout: Option<Json<Vec<TextBox>>>,
     pub views: i32,
     pub likes: i32,
+    pub hot_score: f64,
+    pub rising_score: f64,
     pub created_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LikeResponse {
+    pub liked: bool,
+    pub likes: i32,
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
@@ -77,39 +181,507 @@ pub struct CreateMemeRequest {
     #[validate(length(min = 1, max = 100))]
     pub title: String,
     
+    // Shorthand for the "top" and "bottom" boxes of the classic layout
+    #[validate(length(max = 500))]
     pub top_text: Option<String>,
+    #[validate(length(max = 500))]
     pub bottom_text: Option<String>,
+    
+    // Text per layout box, keyed by box name
+    #[serde(default)]
+    #[validate(custom = "validate_captions")]
+    pub text_boxes: HashMap<String, String>,
+    
+    // Free-form captions drawn on top of the layout
+    #[serde(default)]
+    #[validate(length(max = 20))]
+    #[validate]
+    pub overlays: Vec<TextOverlay>,
+    
+    #[validate(length(min = 1, max = 100))]
     pub template_name: Option<String>,
     
-    // Base64 encoded image or template selection
+    // Base64 encoded image; takes precedence over template_name
     pub image_data: Option<String>,
+    
+    // ID returned by POST /api/uploads; takes precedence over image_data
+    pub upload_id: Option<Uuid>,
+    
+    // Added to any #hashtags found in the title and captions
+    #[serde(default)]
+    #[validate(custom = "validate_tags")]
+    pub tags: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl CreateMemeRequest {
+    /// Box captions with the classic `top_text`/`bottom_text` shorthand merged in.
+    pub fn captions(&self) -> HashMap<String, String> {
+        let mut captions = self.text_boxes.clone();
+        if let Some(text) = &self.top_text {
+            captions.entry("top".to_string()).or_insert_with(|| text.clone());
+        }
+        if let Some(text) = &self.bottom_text {
+            captions.entry("bottom".to_string()).or_insert_with(|| text.clone());
+        }
+        captions
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateMemeRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub title: Option<String>,
+    
+    // Shorthand for the "top" and "bottom" boxes; an empty string clears a box
+    #[validate(length(max = 500))]
+    pub top_text: Option<String>,
+    #[validate(length(max = 500))]
+    pub bottom_text: Option<String>,
+    
+    // Replaces all box captions when present
+    #[validate(custom = "validate_captions")]
+    pub text_boxes: Option<HashMap<String, String>>,
+    
+    // Replaces the meme's tags when present; #hashtags are still added
+    #[validate(custom = "validate_tags")]
+    pub tags: Option<Vec<String>>,
+}
+
+impl UpdateMemeRequest {
+    pub fn changes_captions(&self) -> bool {
+        self.top_text.is_some() || self.bottom_text.is_some() || self.text_boxes.is_some()
+    }
+    
+    /// Applies the requested caption changes on top of `current`.
+    pub fn apply_captions(&self, current: HashMap<String, String>) -> HashMap<String, String> {
+        let mut captions = self.text_boxes.clone().unwrap_or(current);
+        if let Some(text) = &self.top_text {
+            captions.insert("top".to_string(), text.clone());
+        }
+        if let Some(text) = &self.bottom_text {
+            captions.insert("bottom".to_string(), text.clone());
+        }
+        captions.retain(|_, text| !text.trim().is_empty());
+        captions
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Upload {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub image_url: String,
+    pub format: String,
+    pub width: i32,
+    pub height: i32,
+    pub size_bytes: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct MemeTemplate {
+    pub id: Uuid,
+    pub name: String,
+    pub image_url: String,
+    pub description: Option<String>,
+    pub layout: Option<Json<Vec<TextBox>>>,
+    pub usage_count: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+impl MemeTemplate {
+    /// Text boxes for this template, falling back to the classic top/bottom pair.
+    pub fn text_boxes(&self) -> Vec<TextBox> {
+        match &self.layout {
+            Some(Json(boxes)) if !boxes.is_empty() => boxes.clone(),
+            _ => TextBox::classic_layout(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TextAlign {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VerticalAlign {
+    Top,
+    #[default]
+    Middle,
+    Bottom,
+}
+
+/// A named caption area on a template. Geometry is expressed as fractions of
+/// the image size so the same layout works at any resolution.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TextBox {
+    pub name: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    #[serde(default)]
+    pub align: TextAlign,
+    #[serde(default)]
+    pub valign: VerticalAlign,
+    // In pixels; defaults to an eighth of the image height
+    pub max_font_size: Option<f32>,
+    // Degrees clockwise around the box centre
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+impl TextBox {
+    pub fn classic_layout() -> Vec<TextBox> {
+        vec![
+            TextBox {
+                name: "top".to_string(),
+                x: 0.05,
+                y: 0.02,
+                width: 0.9,
+                height: 0.31,
+                align: TextAlign::Center,
+                valign: VerticalAlign::Top,
+                max_font_size: None,
+                rotation: 0.0,
+            },
+            TextBox {
+                name: "bottom".to_string(),
+                x: 0.05,
+                y: 0.67,
+                width: 0.9,
+                height: 0.31,
+                align: TextAlign::Center,
+                valign: VerticalAlign::Bottom,
+                max_font_size: None,
+                rotation: 0.0,
+            },
+        ]
+    }
+}
+
+/// A caption placed freely on the image. `x` and `y` are fractions of the
+/// image size and mark the anchor point selected by `align`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Validate)]
+pub struct TextOverlay {
+    #[validate(length(min = 1, max = 500))]
+    pub text: String,
+    
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub x: f32,
+    
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub y: f32,
+    
+    #[serde(default = "default_overlay_font_size")]
+    #[validate(range(min = 8.0, max = 300.0))]
+    pub font_size: f32,
+    
+    #[serde(default = "default_overlay_color")]
+    #[validate(custom = "validate_color")]
+    pub color: String,
+    
+    #[serde(default = "default_overlay_stroke_color")]
+    #[validate(custom = "validate_color")]
+    pub stroke_color: String,
+    
+    #[serde(default = "default_overlay_stroke_width")]
+    #[validate(range(min = 0.0, max = 20.0))]
+    pub stroke_width: f32,
+    
+    #[serde(default)]
+    pub align: TextAlign,
+}
+
+fn default_overlay_font_size() -> f32 {
+    48.0
+}
+
+fn default_overlay_color() -> String {
+    "white".to_string()
+}
+
+fn default_overlay_stroke_color() -> String {
+    "black".to_string()
+}
+
+fn default_overlay_stroke_width() -> f32 {
+    3.0
+}
+
+fn validate_color(color: &str) -> Result<(), ValidationError> {
+    match crate::renderer::parse_color(color) {
+        Some(_) => Ok(()),
+        None => Err(ValidationError::new("invalid_color")),
+    }
+}
+
+fn validate_layout(layout: &[TextBox]) -> Result<(), ValidationError> {
+    let mut names = HashSet::new();
+    for text_box in layout {
+        if text_box.name.is_empty() || text_box.name.len() > 50 {
+            return Err(ValidationError::new("invalid_box_name"));
+        }
+        if !names.insert(text_box.name.as_str()) {
+            return Err(ValidationError::new("duplicate_box_name"));
+        }
+        let inside = |start: f32, size: f32| {
+            start >= 0.0 && size > 0.0 && start + size <= 1.0
+        };
+        if !inside(text_box.x, text_box.width) || !inside(text_box.y, text_box.height) {
+            return Err(ValidationError::new("box_out_of_bounds"));
+        }
+        if text_box.max_font_size.map(|s| s <= 0.0).unwrap_or(false) || !text_box.rotation.is_finite() {
+            return Err(ValidationError::new("invalid_box_style"));
+        }
+    }
+    Ok(())
+}
+
+fn validate_captions(captions: &HashMap<String, String>) -> Result<(), ValidationError> {
+    if captions.len() > 20 || captions.values().any(|text| text.chars().count() > 500) {
+        return Err(ValidationError::new("captions_too_long"));
+    }
+    Ok(())
+}
+
+fn validate_tags(tags: &[String]) -> Result<(), ValidationError> {
+    if tags.len() > crate::tags::MAX_TAGS || tags.iter().any(|tag| crate::tags::normalize_tag(tag).is_none()) {
+        return Err(ValidationError::new("invalid_tags"));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateTemplateRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    
+    #[validate(length(max = 1000))]
+    pub description: Option<String>,
+    
+    // Base64 encoded base image
+    #[validate(length(min = 1))]
+    pub image_data: String,
+    
+    // Defaults to the classic top/bottom layout
+    #[validate(custom = "validate_layout")]
+    pub layout: Option<Vec<TextBox>>,
+}
+
+/// A comment joined with its author.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Comment {
+    pub id: Uuid,
+    pub meme_id: Uuid,
+    // Kept for ownership checks; clients only see the author's username
+    #[serde(skip)]
+    pub user_id: Uuid,
+    pub username: String,
+    pub avatar_url: Option<String>,
+    pub parent_id: Option<Uuid>,
+    pub content: String,
+    pub reply_count: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateCommentRequest {
+    #[validate(length(min = 1, max = 2000))]
+    pub content: String,
+    
+    // Reply to another comment on the same meme
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateCommentRequest {
+    #[validate(length(min = 1, max = 2000))]
+    pub content: String,
+}
+
+/// A meme as returned by the API, joined with its author. `Meme` is the raw
+/// row and stays internal.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct MemeResponse {
     pub id: Uuid,
     pub title: String,
     pub image_url: String,
     pub top_text: Option<String>,
     pub bottom_text: Option<String>,
+    pub template_name: Option<String>,
+    pub captions: Option<Json<HashMap<String, String>>>,
+    pub overlays: Option<Json<Vec<TextOverlay>>>,
     pub username: String,
+    pub avatar_url: Option<String>,
+    pub tags: Vec<String>,
     pub views: i32,
     pub likes: i32,
+    // Feed ranking keys, kept for building cursors
+    #[serde(skip)]
+    pub hot_score: f64,
+    #[serde(skip)]
+    pub rising_score: f64,
+    // Whether the requesting user has liked this meme
+    pub liked_by_me: bool,
+    pub comment_count: i64,
     pub created_at: DateTime<Utc>,
+    // Only set for memes in the trash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[sqlx(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purge_at: Option<DateTime<Utc>>,
+}
+
+/// A tag and how many live memes carry it, for autocomplete and trending.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct TagCount {
+    pub name: String,
+    pub meme_count: i64,
+}
+
+/// A notification as shown to its recipient. Several actors doing the same
+/// thing to the same meme share one notification until it is read.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Notification {
+    pub id: Uuid,
+    pub kind: String,
+    pub meme_id: Option<Uuid>,
+    pub meme_title: Option<String>,
+    // The most recent actor
+    pub actor_username: String,
+    pub actor_avatar_url: Option<String>,
+    pub actor_count: i32,
+    #[sqlx(default)]
+    pub message: String,
+    pub read: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NotificationList {
+    pub notifications: Vec<Notification>,
+    pub unread_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct MarkReadRequest {
+    // Notifications to mark read; all of them when absent
+    #[validate(length(min = 1, max = 100))]
+    pub ids: Option<Vec<Uuid>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnreadCount {
+    pub unread_count: i64,
+}
+
+/// Which notifications a user wants. Everything is on until they opt out.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NotificationPreferences {
+    pub likes: bool,
+    pub comments: bool,
+    pub follows: bool,
+}
+
+impl Default for NotificationPreferences {
+    fn default() -> Self {
+        NotificationPreferences {
+            likes: true,
+            comments: true,
+            follows: true,
+        }
+    }
+}
+
+impl NotificationPreferences {
+    pub fn allows(&self, kind: crate::notifications::NotificationKind) -> bool {
+        use crate::notifications::NotificationKind;
+        match kind {
+            NotificationKind::Like => self.likes,
+            NotificationKind::Comment => self.comments,
+            NotificationKind::Follow => self.follows,
+        }
+    }
+}
+
+/// Preference changes; omitted fields are kept.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateNotificationPreferences {
+    pub likes: Option<bool>,
+    pub comments: Option<bool>,
+    pub follows: Option<bool>,
+}
+
+/// A search hit: the meme, its relevance and a snippet of the matching text
+/// with matches wrapped in `<mark>`. The snippet is HTML-escaped.
+#[derive(Debug, Serialize, FromRow)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub meme: MemeResponse,
+    pub rank: f32,
+    pub highlight: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // user id
     pub username: String,
+    pub sid: String, // session id
     pub exp: usize, // expiration
 }
 
+/// The user behind an authenticated request, loaded from their live session.
+///
+/// Declare it as a handler argument to require authentication, or use
+/// `OptionalAuthUser` where signing in is optional.
+#[derive(Debug, Clone, FromRow)]
+pub struct AuthUser {
+    pub id: Uuid,
+    pub username: String,
+    pub roles: Vec<String>,
+    pub session_id: Uuid,
+    /// When the access token this request was made with expires.
+    #[sqlx(skip)]
+    pub token_expires_at: Option<DateTime<Utc>>,
+}
+
+impl AuthUser {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+}
+
+/// One failed validation rule on a request field.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldError {
+    pub code: String,
+    pub message: String,
+    pub params: HashMap<String, serde_json::Value>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiResponse<T> {
     pub success: bool,
     pub data: Option<T>,
     pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    // Field path -> failures, only present on validation errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<HashMap<String, Vec<FieldError>>>,
+    // Opaque cursor for the next page of a paginated list; absent on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
 impl<T> ApiResponse<T> {
@@ -118,6 +690,16 @@ impl<T> ApiResponse<T> {
             success: true,
             data: Some(data),
             error: None,
+            code: None,
+            errors: None,
+            next_cursor: None,
+        }
+    }
+    
+    pub fn page(data: T, next_cursor: Option<String>) -> Self {
+        ApiResponse {
+            next_cursor,
+            ..ApiResponse::success(data)
         }
     }
     
@@ -126,6 +708,102 @@ impl<T> ApiResponse<T> {
             success: false,
             data: None,
             error: Some(message),
+            code: None,
+            errors: None,
+            next_cursor: None,
         }
     }
+    
+    pub fn error_with_code(code: &str, message: String) -> Self {
+        ApiResponse {
+            success: false,
+            data: None,
+            error: Some(message),
+            code: Some(code.to_string()),
+            errors: None,
+            next_cursor: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn captions(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn update(top: Option<&str>, bottom: Option<&str>, boxes: Option<&[(&str, &str)]>) -> UpdateMemeRequest {
+        UpdateMemeRequest {
+            title: None,
+            top_text: top.map(str::to_string),
+            bottom_text: bottom.map(str::to_string),
+            text_boxes: boxes.map(captions),
+            tags: None,
+        }
+    }
+
+    #[test]
+    fn test_oversized_shorthand_caption_is_rejected() {
+        let request = |text: String| CreateMemeRequest {
+            title: "t".to_string(),
+            top_text: Some(text),
+            bottom_text: None,
+            text_boxes: HashMap::new(),
+            overlays: Vec::new(),
+            template_name: None,
+            image_data: None,
+            upload_id: None,
+            tags: Vec::new(),
+        };
+
+        assert!(request("é".repeat(500)).validate().is_ok());
+        assert!(request("a".repeat(501)).validate().is_err());
+    }
+
+    #[test]
+    fn test_avatar_url_validation() {
+        let profile = |url: &str| UpdateProfileRequest {
+            display_name: None,
+            bio: None,
+            avatar_url: Some(url.to_string()),
+        };
+
+        for url in ["https://example.com/me.png", "http://example.com/me.png", "/uploads/avatars/me.png", ""] {
+            assert!(profile(url).validate().is_ok(), "{}", url);
+        }
+
+        let long = format!("https://example.com/{}.png", "a".repeat(500));
+        for url in [
+            "javascript:alert(1)",
+            "data:image/png;base64,AAAA",
+            "avatars/me.png",
+            "/uploads/../secret",
+            "//example.com/me.png",
+            &long,
+        ] {
+            assert!(profile(url).validate().is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_apply_captions() {
+        let current = captions(&[("top", "old top"), ("bottom", "old bottom")]);
+
+        // Untouched boxes keep their text
+        let kept = update(Some("new top"), None, None).apply_captions(current.clone());
+        assert_eq!(kept, captions(&[("top", "new top"), ("bottom", "old bottom")]));
+
+        // An empty string clears a box
+        let cleared = update(None, Some("  "), None).apply_captions(current.clone());
+        assert_eq!(cleared, captions(&[("top", "old top")]));
+
+        // text_boxes replaces everything, then the shorthands apply on top
+        let replaced = update(None, Some("b"), Some(&[("left", "l"), ("top", "")])).apply_captions(current.clone());
+        assert_eq!(replaced, captions(&[("left", "l"), ("bottom", "b")]));
+
+        assert_eq!(update(None, None, None).apply_captions(current.clone()), current);
+        assert!(update(None, Some(&"a".repeat(501)), None).validate().is_err());
+    }
 }
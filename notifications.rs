@@ -0,0 +1,89 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::database;
+use crate::realtime::{self, Event};
+
+/// What a notification is about. Stored as its `as_str` name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationKind {
+    Like,
+    Comment,
+    Follow,
+}
+
+impl NotificationKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NotificationKind::Like => "like",
+            NotificationKind::Comment => "comment",
+            NotificationKind::Follow => "follow",
+        }
+    }
+}
+
+/// Builds the display text, e.g. `alice and 12 others liked your meme "Monday"`.
+pub fn describe(kind: &str, actor: &str, actor_count: i32, meme_title: Option<&str>) -> String {
+    let who = match actor_count {
+        ..=1 => actor.to_string(),
+        2 => format!("{} and 1 other", actor),
+        n => format!("{} and {} others", actor, n - 1),
+    };
+    let meme = match meme_title {
+        Some(title) => format!("your meme \"{}\"", title),
+        None => "your meme".to_string(),
+    };
+
+    match kind {
+        "like" => format!("{} liked {}", who, meme),
+        "comment" => format!("{} commented on {}", who, meme),
+        "follow" => format!("{} started following you", who),
+        _ => format!("{} interacted with {}", who, meme),
+    }
+}
+
+/// Tells `recipient_id` that `actor_id` did something, folding it into their
+/// unread notification for the same kind and meme if there is one. Skips
+/// self-actions and kinds the recipient turned off, and pushes the new
+/// unread count to the recipient's open streams.
+pub async fn notify(
+    pool: &PgPool,
+    recipient_id: Uuid,
+    actor_id: Uuid,
+    kind: NotificationKind,
+    meme_id: Option<Uuid>,
+) {
+    if recipient_id == actor_id {
+        return;
+    }
+
+    let result = async {
+        let preferences = database::get_notification_preferences(pool, recipient_id).await?;
+        if !preferences.allows(kind) {
+            return Ok(None);
+        }
+        database::add_notification(pool, recipient_id, actor_id, kind.as_str(), meme_id).await?;
+        database::count_unread_notifications(pool, recipient_id).await.map(Some)
+    }
+    .await;
+
+    match result {
+        Ok(Some(unread_count)) => {
+            realtime::publish(pool, &Event::Notification { user_id: recipient_id, unread_count }).await;
+        }
+        Ok(None) => {}
+        Err(e) => log::warn!("Failed to notify user {} of {}: {}", recipient_id, kind.as_str(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_aggregates_actors() {
+        assert_eq!(describe("like", "alice", 1, Some("Monday")), "alice liked your meme \"Monday\"");
+        assert_eq!(describe("comment", "bob", 2, None), "bob and 1 other commented on your meme");
+        assert_eq!(describe("follow", "carol", 13, None), "carol and 12 others started following you");
+    }
+}
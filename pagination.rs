@@ -0,0 +1,274 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{DateTime, Duration, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::MemeResponse;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+/// Orderings a meme feed can be paged through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemeSort {
+    /// Newest first, keyed on `(created_at, id)`.
+    New,
+    /// Most liked first, keyed on `(likes, id)`.
+    Top,
+    /// Highest `hot_score` first, see `ranking`.
+    Hot,
+    /// Highest `rising_score` first, see `ranking`.
+    Rising,
+}
+
+impl MemeSort {
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "new" => Ok(MemeSort::New),
+            "top" => Ok(MemeSort::Top),
+            "hot" => Ok(MemeSort::Hot),
+            "rising" => Ok(MemeSort::Rising),
+            _ => Err(AppError::BadRequest("sort must be one of: new, top, hot, rising".to_string())),
+        }
+    }
+
+    /// The `ORDER BY` columns; the cursor compares against the same tuple.
+    pub fn key_columns(self) -> &'static str {
+        match self {
+            MemeSort::New => "m.created_at, m.id",
+            MemeSort::Top => "m.likes, m.id",
+            MemeSort::Hot => "m.hot_score, m.id",
+            MemeSort::Rising => "m.rising_score, m.id",
+        }
+    }
+
+    pub fn order_by(self) -> &'static str {
+        match self {
+            MemeSort::New => "m.created_at DESC, m.id DESC",
+            MemeSort::Top => "m.likes DESC, m.id DESC",
+            MemeSort::Hot => "m.hot_score DESC, m.id DESC",
+            MemeSort::Rising => "m.rising_score DESC, m.id DESC",
+        }
+    }
+}
+
+/// How far back a feed reaches, by creation time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemeWindow {
+    Day,
+    Week,
+    Month,
+    All,
+}
+
+impl MemeWindow {
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "day" => Ok(MemeWindow::Day),
+            "week" => Ok(MemeWindow::Week),
+            "month" => Ok(MemeWindow::Month),
+            "all" => Ok(MemeWindow::All),
+            _ => Err(AppError::BadRequest("window must be one of: day, week, month, all".to_string())),
+        }
+    }
+
+    /// The oldest `created_at` included, or `None` for no limit.
+    pub fn since(self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            MemeWindow::Day => Some(now - Duration::days(1)),
+            MemeWindow::Week => Some(now - Duration::weeks(1)),
+            MemeWindow::Month => Some(now - Duration::days(30)),
+            MemeWindow::All => None,
+        }
+    }
+}
+
+/// Position after the last meme of a page. Clients only see it as an opaque
+/// string.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Cursor {
+    New { created_at: DateTime<Utc>, id: Uuid },
+    Top { likes: i32, id: Uuid },
+    Hot { score: f64, id: Uuid },
+    Rising { score: f64, id: Uuid },
+}
+
+impl Cursor {
+    pub fn after(meme: &MemeResponse, sort: MemeSort) -> Self {
+        match sort {
+            MemeSort::New => Cursor::New { created_at: meme.created_at, id: meme.id },
+            MemeSort::Top => Cursor::Top { likes: meme.likes, id: meme.id },
+            MemeSort::Hot => Cursor::Hot { score: meme.hot_score, id: meme.id },
+            MemeSort::Rising => Cursor::Rising { score: meme.rising_score, id: meme.id },
+        }
+    }
+
+    pub fn sort(&self) -> MemeSort {
+        match self {
+            Cursor::New { .. } => MemeSort::New,
+            Cursor::Top { .. } => MemeSort::Top,
+            Cursor::Hot { .. } => MemeSort::Hot,
+            Cursor::Rising { .. } => MemeSort::Rising,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        encode_cursor(self)
+    }
+
+    pub fn decode(value: &str) -> Result<Self, AppError> {
+        decode_cursor(value)
+    }
+}
+
+/// Encodes any cursor as URL-safe base64 JSON.
+pub fn encode_cursor<T: Serialize>(cursor: &T) -> String {
+    let json = serde_json::to_vec(cursor).expect("cursor serializes");
+    URL_SAFE_NO_PAD.encode(json)
+}
+
+pub fn decode_cursor<T: DeserializeOwned>(value: &str) -> Result<T, AppError> {
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .ok_or_else(|| AppError::BadRequest("Invalid cursor".to_string()))
+}
+
+/// Trims the extra row fetched past `limit` and returns the cursor for the
+/// next page, built from the last item kept, if there is one.
+pub fn finish_page<T>(items: &mut Vec<T>, limit: i64, cursor: impl FnOnce(&T) -> String) -> Option<String> {
+    if items.len() as i64 <= limit {
+        return None;
+    }
+
+    items.truncate(limit as usize);
+    items.last().map(cursor)
+}
+
+/// Reads `limit`, defaulting to `DEFAULT_PAGE_SIZE` and clamped to `max`.
+pub fn page_size(query: &HashMap<String, String>, max: i64) -> Result<i64, AppError> {
+    let limit = match query.get("limit") {
+        Some(limit) => limit
+            .parse::<i64>()
+            .map_err(|_| AppError::BadRequest("limit must be a number".to_string()))?,
+        None => DEFAULT_PAGE_SIZE,
+    };
+
+    Ok(limit.clamp(1, max))
+}
+
+/// Narrows a meme feed; the default is every live meme.
+#[derive(Debug, Clone, Default)]
+pub struct MemeFilter {
+    pub author_id: Option<Uuid>,
+    pub tag: Option<String>,
+    /// Only memes by users this user follows.
+    pub followed_by: Option<Uuid>,
+}
+
+/// Position after the last entry of a follower or following list.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FollowCursor {
+    pub followed_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+/// Position after the last notification of a page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationCursor {
+    pub updated_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+/// A requested page: its ordering and time window, where it starts and how
+/// many rows it holds.
+#[derive(Debug, Clone)]
+pub struct PageRequest {
+    pub sort: MemeSort,
+    pub window: MemeWindow,
+    pub after: Option<Cursor>,
+    pub limit: i64,
+}
+
+impl PageRequest {
+    /// Reads `sort`, `window`, `cursor` and `limit` from a query string. The
+    /// page size is clamped to `MAX_PAGE_SIZE`, and a cursor must match the
+    /// sort it was issued for.
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Self, AppError> {
+        let after = query.get("cursor").map(|c| Cursor::decode(c)).transpose()?;
+        let sort = match query.get("sort") {
+            Some(sort) => MemeSort::parse(sort)?,
+            None => after.as_ref().map(Cursor::sort).unwrap_or(MemeSort::New),
+        };
+        if after.as_ref().is_some_and(|c| c.sort() != sort) {
+            return Err(AppError::BadRequest("Cursor does not match sort".to_string()));
+        }
+        let window = match query.get("window") {
+            Some(window) => MemeWindow::parse(window)?,
+            None => MemeWindow::All,
+        };
+
+        Ok(PageRequest {
+            sort,
+            window,
+            after,
+            limit: page_size(query, MAX_PAGE_SIZE)?,
+        })
+    }
+
+    /// `finish_page` for a feed, with the cursor for this sort.
+    pub fn finish(&self, memes: &mut Vec<MemeResponse>) -> Option<String> {
+        finish_page(memes, self.limit, |meme| Cursor::after(meme, self.sort).encode())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_cursor_round_trips() {
+        let cursor = Cursor::Top { likes: 42, id: Uuid::new_v4() };
+        assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+        assert!(Cursor::decode("not-a-cursor").is_err());
+    }
+
+    #[test]
+    fn test_page_request_clamps_limit_and_checks_sort() {
+        let page = PageRequest::from_query(&query(&[("limit", "1000000")])).unwrap();
+        assert_eq!(page.limit, MAX_PAGE_SIZE);
+        assert_eq!(page.sort, MemeSort::New);
+
+        let cursor = Cursor::Top { likes: 1, id: Uuid::new_v4() }.encode();
+        let page = PageRequest::from_query(&query(&[("cursor", &cursor)])).unwrap();
+        assert_eq!(page.sort, MemeSort::Top);
+        assert!(PageRequest::from_query(&query(&[("cursor", &cursor), ("sort", "new")])).is_err());
+        assert!(PageRequest::from_query(&query(&[("window", "year")])).is_err());
+    }
+
+    #[test]
+    fn test_finish_page_trims_the_extra_row() {
+        let mut items = vec![1, 2, 3];
+        assert_eq!(finish_page(&mut items, 2, |n| n.to_string()), Some("2".to_string()));
+        assert_eq!(items, [1, 2]);
+        assert_eq!(finish_page(&mut items, 2, |n| n.to_string()), None);
+        assert_eq!(items, [1, 2]);
+    }
+
+    #[test]
+    fn test_window_cutoffs() {
+        let now = Utc::now();
+        assert_eq!(MemeWindow::Day.since(now), Some(now - Duration::hours(24)));
+        assert_eq!(MemeWindow::Week.since(now), Some(now - Duration::days(7)));
+        assert_eq!(MemeWindow::Month.since(now), Some(now - Duration::days(30)));
+        assert_eq!(MemeWindow::All.since(now), None);
+        assert_eq!(MemeWindow::parse("week").unwrap(), MemeWindow::Week);
+    }
+}
@@ -0,0 +1,80 @@
+use std::time::Duration as StdDuration;
+
+use chrono::Duration;
+use sqlx::PgPool;
+
+use crate::config;
+use crate::database;
+
+/// How often feed scores are recomputed, and how old a meme can be before
+/// it drops out of the hot and rising feeds.
+#[derive(Debug, Clone)]
+pub struct RankingConfig {
+    pub refresh_interval: StdDuration,
+    pub horizon: Duration,
+}
+
+impl Default for RankingConfig {
+    fn default() -> Self {
+        RankingConfig {
+            refresh_interval: StdDuration::from_secs(5 * 60),
+            horizon: Duration::days(30),
+        }
+    }
+}
+
+impl RankingConfig {
+    /// Reads `MEME_SCORE_REFRESH_SECONDS` and `MEME_SCORE_HORIZON_DAYS`.
+    pub fn from_env() -> Self {
+        let defaults = RankingConfig::default();
+        RankingConfig {
+            refresh_interval: config::read_seconds("MEME_SCORE_REFRESH_SECONDS")
+                .unwrap_or(defaults.refresh_interval),
+            horizon: config::read_days("MEME_SCORE_HORIZON_DAYS").unwrap_or(defaults.horizon),
+        }
+    }
+}
+
+/// Recomputes `hot_score` and `rising_score` on the configured interval for
+/// the life of the server, starting immediately.
+pub fn spawn_score_task(pool: PgPool, ranking: RankingConfig) {
+    config::spawn_periodic(ranking.refresh_interval, move || {
+        let pool = pool.clone();
+        async move {
+            match database::refresh_meme_scores(&pool, ranking.horizon).await {
+                Ok(count) => log::debug!("Refreshed feed scores for {} memes", count),
+                Err(e) => log::error!("Failed to refresh feed scores: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_env_defaults() {
+        std::env::remove_var("MEME_SCORE_REFRESH_SECONDS");
+        std::env::remove_var("MEME_SCORE_HORIZON_DAYS");
+        let config = RankingConfig::from_env();
+        assert_eq!(config.refresh_interval, StdDuration::from_secs(300));
+        assert_eq!(config.horizon, Duration::days(30));
+
+        // Zero, unparsable and overflowing values keep the defaults
+        std::env::set_var("MEME_SCORE_REFRESH_SECONDS", "0");
+        std::env::set_var("MEME_SCORE_HORIZON_DAYS", "9223372036854775807");
+        let config = RankingConfig::from_env();
+        assert_eq!(config.refresh_interval, StdDuration::from_secs(300));
+        assert_eq!(config.horizon, Duration::days(30));
+
+        std::env::set_var("MEME_SCORE_REFRESH_SECONDS", "60");
+        std::env::set_var("MEME_SCORE_HORIZON_DAYS", "7");
+        let config = RankingConfig::from_env();
+        assert_eq!(config.refresh_interval, StdDuration::from_secs(60));
+        assert_eq!(config.horizon, Duration::days(7));
+
+        std::env::remove_var("MEME_SCORE_REFRESH_SECONDS");
+        std::env::remove_var("MEME_SCORE_HORIZON_DAYS");
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::time::Duration;
+
+use actix_web::rt;
+use actix_web::web::Bytes;
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+use crate::database;
+
+/// The Postgres `NOTIFY` channel every instance publishes to and listens on.
+pub const EVENTS_CHANNEL: &str = "mememage_events";
+/// Events buffered per connection before a slow client starts skipping them.
+const EVENT_BUFFER: usize = 256;
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// How often a signed-in stream checks that its session is still live.
+const SESSION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// How long browsers wait before reconnecting a dropped stream.
+const CLIENT_RETRY_MS: u64 = 5000;
+
+/// Something that happened on one instance that connected clients on every
+/// instance may need to hear about. Sent through Postgres as JSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    MemeCreated { meme_id: Uuid, author_id: Uuid, username: String },
+    MemeLikes { meme_id: Uuid, likes: i32 },
+    MemeViews { meme_id: Uuid, views: i32 },
+    Notification { user_id: Uuid, unread_count: i64 },
+    /// Keeps open streams' follow lists current; never sent to clients.
+    FollowChanged { follower_id: Uuid, followee_id: Uuid, following: bool },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::MemeCreated { .. } => "meme_created",
+            Event::MemeLikes { .. } => "meme_likes",
+            Event::MemeViews { .. } => "meme_views",
+            Event::Notification { .. } => "notification",
+            Event::FollowChanged { .. } => "follow_changed",
+        }
+    }
+}
+
+/// Fans events received from Postgres out to this instance's streams.
+#[derive(Clone)]
+pub struct EventHub {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for EventHub {
+    fn default() -> Self {
+        EventHub {
+            sender: broadcast::channel(EVENT_BUFFER).0,
+        }
+    }
+}
+
+impl EventHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    /// Hands an event to every open stream on this instance.
+    pub fn dispatch(&self, event: Event) {
+        // Only fails when nobody is connected
+        let _ = self.sender.send(event);
+    }
+}
+
+/// One connected client: who they are and whose new memes they want.
+#[derive(Debug, Clone, Default)]
+pub struct Subscriber {
+    pub user_id: Option<Uuid>,
+    pub following: HashSet<Uuid>,
+}
+
+impl Subscriber {
+    /// The SSE frame this client should receive for `event`, if any. Follow
+    /// changes by this user update their follow list instead.
+    pub fn frame(&mut self, event: &Event) -> Option<Bytes> {
+        let data = match event {
+            Event::MemeCreated { meme_id, author_id, username } => {
+                if !self.following.contains(author_id) {
+                    return None;
+                }
+                json!({ "meme_id": meme_id, "username": username })
+            }
+            Event::MemeLikes { meme_id, likes } => json!({ "meme_id": meme_id, "likes": likes }),
+            Event::MemeViews { meme_id, views } => json!({ "meme_id": meme_id, "views": views }),
+            Event::Notification { user_id, unread_count } => {
+                if self.user_id != Some(*user_id) {
+                    return None;
+                }
+                json!({ "unread_count": unread_count })
+            }
+            Event::FollowChanged { follower_id, followee_id, following } => {
+                if self.user_id == Some(*follower_id) {
+                    if *following {
+                        self.following.insert(*followee_id);
+                    } else {
+                        self.following.remove(followee_id);
+                    }
+                }
+                return None;
+            }
+        };
+
+        Some(Bytes::from(format!("event: {}\ndata: {}\n\n", event.name(), data)))
+    }
+}
+
+/// The sign-in behind a stream. The stream ends when the session is revoked
+/// or the access token it was opened with expires, so the client reconnects
+/// and authenticates again.
+#[derive(Debug, Clone)]
+pub struct StreamSession {
+    pub session_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl StreamSession {
+    async fn is_revoked(&self, pool: &PgPool) -> bool {
+        match database::get_session_user(pool, self.session_id).await {
+            Ok(user) => user.is_none(),
+            // Keep streaming through a database blip; the next check decides
+            Err(e) => {
+                log::warn!("Failed to check realtime stream session: {}", e);
+                false
+            }
+        }
+    }
+
+    fn deadline(&self) -> rt::time::Instant {
+        let remaining = (self.expires_at - Utc::now()).to_std().unwrap_or_default();
+        rt::time::Instant::now() + remaining
+    }
+}
+
+/// Sends `event` to every instance, this one included.
+pub async fn publish(pool: &PgPool, event: &Event) {
+    let payload = serde_json::to_string(event).expect("event serializes");
+    if let Err(e) = database::notify_channel(pool, EVENTS_CHANNEL, &payload).await {
+        log::warn!("Failed to publish {} event: {}", event.name(), e);
+    }
+}
+
+/// Listens on `EVENTS_CHANNEL` over a dedicated connection for the life of
+/// the server, reconnecting after a pause if it drops. Events published
+/// while disconnected are lost; counts catch up with the next change.
+pub fn spawn_listener(database_url: String, hub: EventHub) {
+    rt::spawn(async move {
+        loop {
+            if let Err(e) = listen(&database_url, &hub).await {
+                log::error!("Realtime listener disconnected: {}", e);
+            }
+            rt::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+async fn listen(database_url: &str, hub: &EventHub) -> Result<(), sqlx::Error> {
+    let mut listener = PgListener::connect(database_url).await?;
+    listener.listen(EVENTS_CHANNEL).await?;
+    log::info!("Listening for realtime events on '{}'", EVENTS_CHANNEL);
+
+    loop {
+        let notification = listener.recv().await?;
+        match serde_json::from_str(notification.payload()) {
+            Ok(event) => hub.dispatch(event),
+            Err(e) => log::warn!("Ignoring malformed realtime event: {}", e),
+        }
+    }
+}
+
+/// An SSE body: the `initial` events, then whatever `subscriber` should see
+/// from the hub, with a comment line every `KEEPALIVE_INTERVAL` so proxies
+/// keep the connection open and closed clients are noticed. With a
+/// `session`, the stream ends once that session stops being live.
+pub fn event_stream(
+    hub: &EventHub,
+    pool: PgPool,
+    mut subscriber: Subscriber,
+    session: Option<StreamSession>,
+    initial: Vec<Event>,
+) -> impl Stream<Item = Result<Bytes, Infallible>> {
+    let mut frames = vec![Bytes::from(format!("retry: {}\n\n", CLIENT_RETRY_MS))];
+    frames.extend(initial.iter().filter_map(|event| subscriber.frame(event)));
+
+    let mut keepalive = rt::time::interval(KEEPALIVE_INTERVAL);
+    keepalive.reset();
+    let mut session_check = rt::time::interval(SESSION_CHECK_INTERVAL);
+    session_check.reset();
+    let state = LiveStream {
+        receiver: hub.subscribe(),
+        subscriber,
+        keepalive,
+        session_check,
+        session,
+        pool,
+    };
+
+    let live = stream::unfold(state, |mut state| async move {
+        let frame = state.next_frame().await?;
+        Some((frame, state))
+    });
+
+    stream::iter(frames).chain(live).map(Ok)
+}
+
+struct LiveStream {
+    receiver: broadcast::Receiver<Event>,
+    subscriber: Subscriber,
+    keepalive: rt::time::Interval,
+    session_check: rt::time::Interval,
+    session: Option<StreamSession>,
+    pool: PgPool,
+}
+
+impl LiveStream {
+    /// The next frame to send, or `None` once the stream should end.
+    async fn next_frame(&mut self) -> Option<Bytes> {
+        let deadline = self.session.as_ref().map(StreamSession::deadline);
+
+        loop {
+            tokio::select! {
+                received = self.receiver.recv() => match received {
+                    Ok(event) => {
+                        if let Some(frame) = self.subscriber.frame(&event) {
+                            return Some(frame);
+                        }
+                    }
+                    // Counts are absolute, so later events make up for skipped ones
+                    Err(RecvError::Lagged(skipped)) => {
+                        log::debug!("Realtime stream skipped {} events", skipped);
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+                _ = self.keepalive.tick() => {
+                    return Some(Bytes::from_static(b": keepalive\n\n"));
+                }
+                _ = self.session_check.tick(), if self.session.is_some() => {
+                    if let Some(session) = &self.session {
+                        if session.is_revoked(&self.pool).await {
+                            return None;
+                        }
+                    }
+                }
+                _ = rt::time::sleep_until(deadline.unwrap_or_else(rt::time::Instant::now)), if deadline.is_some() => {
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscriber_only_sees_its_events() {
+        let (me, friend) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut subscriber = Subscriber { user_id: Some(me), following: HashSet::new() };
+        let created = Event::MemeCreated { meme_id: Uuid::new_v4(), author_id: friend, username: "friend".to_string() };
+
+        assert!(subscriber.frame(&created).is_none());
+        assert!(subscriber.frame(&Event::Notification { user_id: friend, unread_count: 1 }).is_none());
+        let frame = subscriber.frame(&Event::Notification { user_id: me, unread_count: 3 }).unwrap();
+        assert_eq!(&frame[..], b"event: notification\ndata: {\"unread_count\":3}\n\n");
+
+        let follow = Event::FollowChanged { follower_id: me, followee_id: friend, following: true };
+        assert!(subscriber.frame(&follow).is_none());
+        assert!(subscriber.frame(&created).unwrap().starts_with(b"event: meme_created\n"));
+    }
+
+    #[test]
+    fn test_event_round_trips_as_json() {
+        let event = Event::MemeLikes { meme_id: Uuid::new_v4(), likes: 7 };
+        let json = serde_json::to_string(&event).unwrap();
+        assert!(json.starts_with("{\"type\":\"meme_likes\""));
+        assert_eq!(serde_json::from_str::<Event>(&json).unwrap(), event);
+    }
+}
@@ -0,0 +1,728 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+
+use crate::image_limits::{ImageLimits, ImageRejection};
+use crate::models::{TextAlign, TextBox, TextOverlay, VerticalAlign};
+
+/// Bundled DejaVu Sans Condensed Bold, see `assets/fonts/LICENSE`.
+const DEFAULT_FONT_PATH: &str = "assets/fonts/DejaVuSansCondensed-Bold.ttf";
+const MIN_FONT_SIZE: f32 = 12.0;
+/// Most glyph area (characters times font size squared) one render may draw
+/// across all overlays: a full 500-character overlay at the largest size.
+const MAX_OVERLAY_GLYPH_AREA: f32 = 500.0 * 300.0 * 300.0;
+
+#[derive(Debug)]
+pub enum RenderError {
+    /// The source image was refused by the configured `ImageLimits`.
+    Rejected(ImageRejection),
+    Failed(String),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::Rejected(rejection) => write!(f, "{}", rejection),
+            RenderError::Failed(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<ImageRejection> for RenderError {
+    fn from(rejection: ImageRejection) -> Self {
+        RenderError::Rejected(rejection)
+    }
+}
+
+/// Renders caption text onto a source image and writes the result to disk.
+///
+/// Implementations must be safe to share between worker threads; handlers
+/// hold a single renderer behind `web::Data` for the lifetime of the server.
+pub trait MemeRenderer: Send + Sync {
+    /// Draws each caption inside its text box, then the free-form overlays on top.
+    fn render(
+        &self,
+        input_path: &str,
+        captions: &[(TextBox, String)],
+        overlays: &[TextOverlay],
+        output_path: &str,
+    ) -> Result<(), RenderError>;
+}
+
+/// Pairs each caption with its box in `layout`, rejecting names the layout
+/// does not define. Empty captions are dropped.
+pub fn fill_layout(
+    layout: &[TextBox],
+    captions: &HashMap<String, String>,
+) -> Result<Vec<(TextBox, String)>, String> {
+    if let Some(unknown) = captions.keys().find(|name| !layout.iter().any(|b| &b.name == *name)) {
+        return Err(format!("Unknown text box '{}'", unknown));
+    }
+
+    Ok(layout
+        .iter()
+        .filter_map(|text_box| {
+            captions
+                .get(&text_box.name)
+                .filter(|text| !text.trim().is_empty())
+                .map(|text| (text_box.clone(), text.clone()))
+        })
+        .collect())
+}
+
+/// Parses `#RRGGBB`, `#RRGGBBAA` or one of a few common colour names.
+pub fn parse_color(value: &str) -> Option<Rgba<u8>> {
+    let named = match value.to_ascii_lowercase().as_str() {
+        "white" => Some([255, 255, 255]),
+        "black" => Some([0, 0, 0]),
+        "red" => Some([255, 0, 0]),
+        "green" => Some([0, 128, 0]),
+        "blue" => Some([0, 0, 255]),
+        "yellow" => Some([255, 255, 0]),
+        _ => None,
+    };
+    if let Some([r, g, b]) = named {
+        return Some(Rgba([r, g, b, 255]));
+    }
+
+    let hex = value.strip_prefix('#')?;
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    let alpha = if hex.len() == 8 { channel(6)? } else { 255 };
+    Some(Rgba([channel(0)?, channel(2)?, channel(4)?, alpha]))
+}
+
+/// Refuses overlays whose text would take too long to draw, whatever the
+/// image size.
+pub fn check_overlay_area(overlays: &[TextOverlay]) -> Result<(), RenderError> {
+    let area: f32 = overlays
+        .iter()
+        .map(|o| o.text.chars().filter(|c| !c.is_whitespace()).count() as f32 * o.font_size * o.font_size)
+        .sum();
+    if area > MAX_OVERLAY_GLYPH_AREA {
+        return Err(ImageRejection::TooLarge("Overlay text is too large to render".to_string()).into());
+    }
+    Ok(())
+}
+
+/// The part of a padded text block that gets rasterized, in block pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Viewport {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Viewport {
+    /// The whole block: the box plus the stroke width on every side.
+    fn full(box_width: f32, box_height: f32, stroke: i32) -> Self {
+        let pad = 2.0 * stroke as f32;
+        Viewport {
+            x: 0,
+            y: 0,
+            width: (box_width + pad).ceil() as usize,
+            height: (box_height + pad).ceil() as usize,
+        }
+    }
+
+    /// The part of a block whose top-left corner sits at `origin` on an
+    /// image of `image_size` that can affect the image: the visible part plus
+    /// `stroke` pixels around it, so outlines at the edges stay whole.
+    fn clipped(self, origin: (f32, f32), image_size: (u32, u32), stroke: i32) -> Option<Self> {
+        let pad = stroke as f32;
+        let clip = |start: f32, block: usize, image: u32| {
+            let from = (-start - pad).floor().max(0.0);
+            let to = (image as f32 - start + pad).ceil().min(block as f32);
+            (to > from).then_some((from as usize, (to - from) as usize))
+        };
+
+        let (x, width) = clip(origin.0, self.width, image_size.0)?;
+        let (y, height) = clip(origin.1, self.height, image_size.1)?;
+        Some(Viewport { x, y, width, height })
+    }
+}
+
+/// Coverage masks for one rendered text box, before compositing.
+struct CaptionMask {
+    width: usize,
+    height: usize,
+    fill: Vec<f32>,
+    outline: Vec<f32>,
+}
+
+/// Pure-Rust renderer built on the `image` crate and a TrueType font.
+pub struct RustMemeRenderer {
+    font: FontVec,
+    limits: ImageLimits,
+}
+
+impl RustMemeRenderer {
+    pub fn new(font_data: Vec<u8>, limits: ImageLimits) -> Result<Self, String> {
+        let font = FontVec::try_from_vec(font_data).map_err(|e| format!("Invalid font: {}", e))?;
+        Ok(RustMemeRenderer { font, limits })
+    }
+
+    pub fn from_font_file<P: AsRef<Path>>(path: P, limits: ImageLimits) -> Result<Self, String> {
+        let path = path.as_ref();
+        let data = fs::read(path)
+            .map_err(|e| format!("Failed to read font {}: {}", path.display(), e))?;
+        Self::new(data, limits)
+    }
+
+    fn draw_caption(&self, image: &mut RgbaImage, text_box: &TextBox, text: &str) {
+        let text = text.trim().to_uppercase();
+        if text.is_empty() {
+            return;
+        }
+
+        let (width, height) = image.dimensions();
+        let box_x = text_box.x * width as f32;
+        let box_y = text_box.y * height as f32;
+        let box_width = (text_box.width * width as f32).max(1.0);
+        let box_height = (text_box.height * height as f32).max(1.0);
+
+        // Start at the box's maximum size and shrink until the caption fits
+        let max_font_size = text_box.max_font_size.unwrap_or(height as f32 / 8.0);
+        let min_font_size = MIN_FONT_SIZE.min(box_height);
+        let mut font_size = max_font_size.min(box_height).max(min_font_size);
+        let mut lines = self.wrap_text(&text, PxScale::from(font_size), box_width);
+        while font_size > min_font_size {
+            let scale = PxScale::from(font_size);
+            let block_height = lines.len() as f32 * self.font.as_scaled(scale).height();
+            let widest = lines
+                .iter()
+                .map(|l| self.line_width(l, scale))
+                .fold(0.0, f32::max);
+            if block_height <= box_height && widest <= box_width {
+                break;
+            }
+            font_size = (font_size - 2.0).max(min_font_size);
+            lines = self.wrap_text(&text, PxScale::from(font_size), box_width);
+        }
+
+        let stroke = (font_size / 16.0).ceil().max(1.0) as i32;
+        let mask = self.rasterize(
+            &lines,
+            PxScale::from(font_size),
+            text_box.align,
+            text_box.valign,
+            box_width,
+            box_height,
+            stroke,
+            Viewport::full(box_width, box_height, stroke),
+        );
+        let center = (box_x + box_width / 2.0, box_y + box_height / 2.0);
+        let angle = text_box.rotation.to_radians();
+        composite(image, &mask, &mask.outline, center, angle, Rgba([0, 0, 0, 255]));
+        composite(image, &mask, &mask.fill, center, angle, Rgba([255, 255, 255, 255]));
+    }
+
+    /// Draws a free-standing overlay anchored at its (x, y) point. Text is not
+    /// wrapped or resized; explicit line breaks are honoured.
+    fn draw_overlay(&self, image: &mut RgbaImage, overlay: &TextOverlay) {
+        let lines: Vec<String> = overlay
+            .text
+            .lines()
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect();
+        if lines.is_empty() {
+            return;
+        }
+
+        let (width, height) = image.dimensions();
+        let scale = PxScale::from(overlay.font_size);
+        let block_width = lines
+            .iter()
+            .map(|l| self.line_width(l, scale))
+            .fold(0.0, f32::max);
+        let block_height = lines.len() as f32 * self.font.as_scaled(scale).height();
+
+        // The anchor is the left edge, centre or right edge of the text block
+        let anchor_x = overlay.x * width as f32;
+        let center_x = match overlay.align {
+            TextAlign::Left => anchor_x + block_width / 2.0,
+            TextAlign::Center => anchor_x,
+            TextAlign::Right => anchor_x - block_width / 2.0,
+        };
+
+        // Only rasterize and stroke the part of the block over the image
+        let stroke = overlay.stroke_width.round() as i32;
+        let block = Viewport::full(block_width, block_height, stroke);
+        let origin = (
+            center_x - block.width as f32 / 2.0,
+            overlay.y * height as f32 - block.height as f32 / 2.0,
+        );
+        let Some(view) = block.clipped(origin, (width, height), stroke) else {
+            return;
+        };
+        let center = (
+            origin.0 + view.x as f32 + view.width as f32 / 2.0,
+            origin.1 + view.y as f32 + view.height as f32 / 2.0,
+        );
+
+        let mask = self.rasterize(
+            &lines,
+            scale,
+            overlay.align,
+            VerticalAlign::Middle,
+            block_width,
+            block_height,
+            stroke,
+            view,
+        );
+        let fill = parse_color(&overlay.color).unwrap_or(Rgba([255, 255, 255, 255]));
+        let outline = parse_color(&overlay.stroke_color).unwrap_or(Rgba([0, 0, 0, 255]));
+        composite(image, &mask, &mask.outline, center, 0.0, outline);
+        composite(image, &mask, &mask.fill, center, 0.0, fill);
+    }
+
+    fn line_width(&self, text: &str, scale: PxScale) -> f32 {
+        let scaled = self.font.as_scaled(scale);
+        let mut width = 0.0;
+        let mut previous = None;
+        for c in text.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(prev) = previous {
+                width += scaled.kern(prev, id);
+            }
+            width += scaled.h_advance(id);
+            previous = Some(id);
+        }
+        width
+    }
+
+    fn wrap_text(&self, text: &str, scale: PxScale, max_width: f32) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut current = String::new();
+
+        for word in text.split_whitespace() {
+            let candidate = if current.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", current, word)
+            };
+
+            if self.line_width(&candidate, scale) > max_width && !current.is_empty() {
+                lines.push(std::mem::replace(&mut current, word.to_string()));
+            } else {
+                current = candidate;
+            }
+        }
+
+        if !current.is_empty() {
+            lines.push(current);
+        }
+
+        lines
+    }
+
+    /// Rasterizes the lines into fill and outline masks covering `view` of
+    /// the box, which is padded by the stroke width on every side. Glyphs
+    /// outside the view are skipped.
+    #[allow(clippy::too_many_arguments)]
+    fn rasterize(
+        &self,
+        lines: &[String],
+        scale: PxScale,
+        align: TextAlign,
+        valign: VerticalAlign,
+        box_width: f32,
+        box_height: f32,
+        stroke: i32,
+        view: Viewport,
+    ) -> CaptionMask {
+        let scaled = self.font.as_scaled(scale);
+        let pad = stroke as f32;
+        let (width, height) = (view.width, view.height);
+        let (left, top_edge) = (view.x as f32, view.y as f32);
+        let mut fill = vec![0.0f32; width * height];
+
+        let line_height = scaled.height();
+        let block_height = lines.len() as f32 * line_height;
+        let top = pad + match valign {
+            VerticalAlign::Top => 0.0,
+            VerticalAlign::Middle => (box_height - block_height) / 2.0,
+            VerticalAlign::Bottom => box_height - block_height,
+        };
+
+        for (i, line) in lines.iter().enumerate() {
+            let line_width = self.line_width(line, scale);
+            let mut caret = pad + match align {
+                TextAlign::Left => 0.0,
+                TextAlign::Center => (box_width - line_width) / 2.0,
+                TextAlign::Right => box_width - line_width,
+            };
+            let baseline = top + i as f32 * line_height + scaled.ascent();
+
+            let mut previous = None;
+            for c in line.chars() {
+                let id = scaled.glyph_id(c);
+                if let Some(prev) = previous {
+                    caret += scaled.kern(prev, id);
+                }
+                let glyph = id.with_scale_and_position(scale, point(caret, baseline));
+                caret += scaled.h_advance(id);
+                previous = Some(id);
+
+                if let Some(outlined) = self.font.outline_glyph(glyph) {
+                    let bounds = outlined.px_bounds();
+                    if bounds.max.x < left
+                        || bounds.max.y < top_edge
+                        || bounds.min.x > left + width as f32
+                        || bounds.min.y > top_edge + height as f32
+                    {
+                        continue;
+                    }
+                    outlined.draw(|gx, gy, c| {
+                        let px = bounds.min.x as i32 + gx as i32 - view.x as i32;
+                        let py = bounds.min.y as i32 + gy as i32 - view.y as i32;
+                        if px >= 0 && py >= 0 && (px as usize) < width && (py as usize) < height {
+                            let idx = py as usize * width + px as usize;
+                            fill[idx] = fill[idx].max(c);
+                        }
+                    });
+                }
+            }
+        }
+
+        let outline = if stroke > 0 {
+            dilate(&fill, width, height, stroke)
+        } else {
+            vec![0.0; fill.len()]
+        };
+        CaptionMask { width, height, fill, outline }
+    }
+}
+
+impl MemeRenderer for RustMemeRenderer {
+    fn render(
+        &self,
+        input_path: &str,
+        captions: &[(TextBox, String)],
+        overlays: &[TextOverlay],
+        output_path: &str,
+    ) -> Result<(), RenderError> {
+        check_overlay_area(overlays)?;
+        let bytes = fs::read(input_path)
+            .map_err(|e| RenderError::Failed(format!("Failed to load image: {}", e)))?;
+        let mut image = self.limits.decode(&bytes)?.to_rgba8();
+        drop(bytes);
+
+        for (text_box, text) in captions {
+            self.draw_caption(&mut image, text_box, text);
+        }
+        for overlay in overlays {
+            self.draw_overlay(&mut image, overlay);
+        }
+
+        save_image(image, output_path).map_err(RenderError::Failed)
+    }
+}
+
+/// Grows a coverage mask by `radius` pixels to produce the caption outline.
+///
+/// Pixels at least half covered seed an exact Euclidean distance transform,
+/// so the cost is linear in the mask size whatever the radius. The outline
+/// fades out over its last pixel and never drops below the original coverage.
+fn dilate(coverage: &[f32], width: usize, height: usize, radius: i32) -> Vec<f32> {
+    // Squared distance to the nearest seed; "infinite" must stay finite here
+    const FAR: f64 = 1e20;
+    let mut distance: Vec<f64> = coverage
+        .iter()
+        .map(|&c| if c >= 0.5 { 0.0 } else { FAR })
+        .collect();
+
+    let mut pass = DistancePass::default();
+    let mut column = vec![0.0f64; height];
+    for x in 0..width {
+        for (y, value) in column.iter_mut().enumerate() {
+            *value = distance[y * width + x];
+        }
+        pass.run(&mut column);
+        for (y, value) in column.iter().enumerate() {
+            distance[y * width + x] = *value;
+        }
+    }
+    for row in distance.chunks_mut(width.max(1)) {
+        pass.run(row);
+    }
+
+    coverage
+        .iter()
+        .zip(&distance)
+        .map(|(&c, &d)| (radius as f32 + 1.0 - d.sqrt() as f32).clamp(0.0, 1.0).max(c))
+        .collect()
+}
+
+/// One axis of the Felzenszwalb–Huttenlocher distance transform: replaces
+/// each squared distance with the lower envelope of the parabolas rooted at
+/// every sample, in linear time. Buffers are kept between lines.
+#[derive(Default)]
+struct DistancePass {
+    input: Vec<f64>,
+    /// Samples whose parabolas form the envelope, left to right.
+    roots: Vec<usize>,
+    /// Where each envelope parabola takes over from the previous one.
+    bounds: Vec<f64>,
+}
+
+impl DistancePass {
+    fn run(&mut self, line: &mut [f64]) {
+        let n = line.len();
+        if n == 0 {
+            return;
+        }
+        self.input.clear();
+        self.input.extend_from_slice(line);
+        self.roots.resize(n, 0);
+        self.bounds.resize(n + 1, 0.0);
+
+        let f = &self.input;
+        let intersect = |p: usize, q: usize| {
+            ((f[q] + (q * q) as f64) - (f[p] + (p * p) as f64)) / (2.0 * (q - p) as f64)
+        };
+
+        let mut k = 0;
+        self.roots[0] = 0;
+        self.bounds[0] = f64::NEG_INFINITY;
+        self.bounds[1] = f64::INFINITY;
+        for q in 1..n {
+            let mut s = intersect(self.roots[k], q);
+            while s <= self.bounds[k] {
+                k -= 1;
+                s = intersect(self.roots[k], q);
+            }
+            k += 1;
+            self.roots[k] = q;
+            self.bounds[k] = s;
+            self.bounds[k + 1] = f64::INFINITY;
+        }
+
+        k = 0;
+        for (q, value) in line.iter_mut().enumerate() {
+            while self.bounds[k + 1] < q as f64 {
+                k += 1;
+            }
+            let offset = q as f64 - self.roots[k] as f64;
+            *value = offset * offset + f[self.roots[k]];
+        }
+    }
+}
+
+/// Blends `color` onto the image through `coverage`, with the mask centred on
+/// `center` and rotated clockwise by `angle` radians.
+fn composite(
+    image: &mut RgbaImage,
+    mask: &CaptionMask,
+    coverage: &[f32],
+    center: (f32, f32),
+    angle: f32,
+    color: Rgba<u8>,
+) {
+    let (width, height) = image.dimensions();
+    let (sin, cos) = angle.sin_cos();
+    let (half_w, half_h) = (mask.width as f32 / 2.0, mask.height as f32 / 2.0);
+
+    // Bounding box of the rotated mask in image space
+    let extent_x = half_w * cos.abs() + half_h * sin.abs();
+    let extent_y = half_w * sin.abs() + half_h * cos.abs();
+    let min_x = (center.0 - extent_x).floor().max(0.0) as u32;
+    let min_y = (center.1 - extent_y).floor().max(0.0) as u32;
+    let max_x = ((center.0 + extent_x).ceil() as u32).min(width);
+    let max_y = ((center.1 + extent_y).ceil() as u32).min(height);
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            // Map the destination pixel centre back into mask space
+            let dx = x as f32 + 0.5 - center.0;
+            let dy = y as f32 + 0.5 - center.1;
+            let mx = dx * cos + dy * sin + half_w;
+            let my = -dx * sin + dy * cos + half_h;
+            if mx < 0.0 || my < 0.0 || mx >= mask.width as f32 || my >= mask.height as f32 {
+                continue;
+            }
+
+            let alpha = coverage[my as usize * mask.width + mx as usize].min(1.0) * color[3] as f32 / 255.0;
+            if alpha <= 0.0 {
+                continue;
+            }
+
+            let pixel = image.get_pixel_mut(x, y);
+            for channel in 0..3 {
+                let blended = color[channel] as f32 * alpha + pixel[channel] as f32 * (1.0 - alpha);
+                pixel[channel] = blended.round() as u8;
+            }
+            pixel[3] = pixel[3].max((alpha * 255.0) as u8);
+        }
+    }
+}
+
+fn save_image(image: RgbaImage, output_path: &str) -> Result<(), String> {
+    let format = ImageFormat::from_path(output_path)
+        .map_err(|e| format!("Unsupported output format: {}", e))?;
+    let image = DynamicImage::ImageRgba8(image);
+
+    // JPEG has no alpha channel
+    let result = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()).save_with_format(output_path, format),
+        _ => image.save_with_format(output_path, format),
+    };
+
+    result.map_err(|e| format!("Failed to save image: {}", e))
+}
+
+/// Builds the renderer selected by the environment.
+///
+/// `MEME_RENDERER=cpp` selects the C++ processor when the `cpp-ffi` feature is
+/// enabled; otherwise the pure-Rust renderer is used with the font at
+/// `MEME_FONT_PATH`, or the bundled font when that is unset.
+pub fn renderer_from_env(limits: &ImageLimits) -> Result<Arc<dyn MemeRenderer>, String> {
+    #[cfg(feature = "cpp-ffi")]
+    {
+        if env::var("MEME_RENDERER").map(|v| v == "cpp").unwrap_or(false) {
+            return Ok(Arc::new(crate::image_ffi::MemeProcessor::with_limits(limits.clone())));
+        }
+    }
+
+    let font_path = env::var("MEME_FONT_PATH").unwrap_or_else(|_| DEFAULT_FONT_PATH.to_string());
+    if !Path::new(&font_path).is_file() {
+        return Err(format!(
+            "Caption font {} not found; set MEME_FONT_PATH to a TrueType font file",
+            font_path
+        ));
+    }
+    Ok(Arc::new(RustMemeRenderer::from_font_file(font_path, limits.clone())?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejects_invalid_font() {
+        assert!(RustMemeRenderer::new(vec![0u8; 16], ImageLimits::default()).is_err());
+    }
+
+    fn captions(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_fill_layout_places_captions_in_layout_order() {
+        let layout = TextBox::classic_layout();
+        let filled = fill_layout(&layout, &captions(&[("bottom", "then this"), ("top", "first")])).unwrap();
+
+        assert_eq!(filled.len(), 2);
+        assert_eq!(filled[0], (layout[0].clone(), "first".to_string()));
+        assert_eq!(filled[1], (layout[1].clone(), "then this".to_string()));
+    }
+
+    #[test]
+    fn test_fill_layout_skips_missing_and_rejects_extra_captions() {
+        let layout = TextBox::classic_layout();
+
+        // Boxes without a caption, or with a blank one, are left empty
+        let filled = fill_layout(&layout, &captions(&[("top", "only top"), ("bottom", "  ")])).unwrap();
+        assert_eq!(filled, vec![(layout[0].clone(), "only top".to_string())]);
+        assert!(fill_layout(&layout, &HashMap::new()).unwrap().is_empty());
+
+        let error = fill_layout(&layout, &captions(&[("top", "a"), ("middle", "b")])).unwrap_err();
+        assert_eq!(error, "Unknown text box 'middle'");
+    }
+
+    #[test]
+    fn test_overlay_viewport_is_clipped_to_the_image() {
+        // A 75000x400 block centred on the left edge of a 600x400 image
+        let block = Viewport::full(74_990.0, 390.0, 5);
+        assert_eq!((block.width, block.height), (75_000, 400));
+        let view = block.clipped((-37_500.0, 0.0), (600, 400), 5).unwrap();
+        assert_eq!(view, Viewport { x: 37_495, y: 0, width: 610, height: 400 });
+
+        // Entirely off the image, even counting the stroke
+        assert!(block.clipped((700.0, 0.0), (600, 400), 5).is_none());
+    }
+
+    #[test]
+    fn test_check_overlay_area() {
+        let overlay = |text: &str, font_size: f32| TextOverlay {
+            text: text.to_string(),
+            x: 0.5,
+            y: 0.5,
+            font_size,
+            color: "white".to_string(),
+            stroke_color: "black".to_string(),
+            stroke_width: 2.0,
+            align: TextAlign::Center,
+        };
+        let largest = overlay(&"W".repeat(500), 300.0);
+        assert!(check_overlay_area(std::slice::from_ref(&largest)).is_ok());
+        assert!(matches!(
+            check_overlay_area(&[largest, overlay("one more", 48.0)]),
+            Err(RenderError::Rejected(ImageRejection::TooLarge(_)))
+        ));
+    }
+
+    #[test]
+    fn test_dilate_grows_mask() {
+        let mut mask = vec![0.0f32; 25];
+        mask[12] = 1.0;
+        let grown = dilate(&mask, 5, 5, 1);
+        assert_eq!(grown[7], 1.0);
+        assert_eq!(grown[11], 1.0);
+        assert_eq!(grown[0], 0.0);
+    }
+
+    #[test]
+    fn test_dilate_cost_does_not_depend_on_radius() {
+        let (width, height) = (1000, 400);
+        let mut mask = vec![0.0f32; width * height];
+        mask[200 * width + 500] = 1.0;
+
+        let started = std::time::Instant::now();
+        let grown = dilate(&mask, width, height, 150);
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+
+        // A disc: full strength out to the radius, nothing past it
+        assert_eq!(grown[200 * width + 650], 1.0);
+        assert_eq!(grown[(200 + 106) * width + 500 + 106], 1.0);
+        assert_eq!(grown[200 * width + 652], 0.0);
+        assert_eq!(grown[(200 + 108) * width + 500 + 108], 0.0);
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#ff8000"), Some(Rgba([255, 128, 0, 255])));
+        assert_eq!(parse_color("#00000080"), Some(Rgba([0, 0, 0, 128])));
+        assert_eq!(parse_color("White"), Some(Rgba([255, 255, 255, 255])));
+        assert_eq!(parse_color("#fff"), None);
+        assert_eq!(parse_color("chartreuse"), None);
+    }
+
+    #[test]
+    fn test_composite_rotates_about_center() {
+        // A 4x2 horizontal bar turned 90 degrees becomes a 2x4 vertical bar
+        let mask = CaptionMask {
+            width: 4,
+            height: 2,
+            fill: vec![1.0; 8],
+            outline: vec![0.0; 8],
+        };
+        let mut image = RgbaImage::from_pixel(8, 8, Rgba([0, 0, 0, 255]));
+        let white = Rgba([255, 255, 255, 255]);
+        composite(&mut image, &mask, &mask.fill, (4.0, 4.0), std::f32::consts::FRAC_PI_2, white);
+
+        assert_eq!(*image.get_pixel(3, 2), white);
+        assert_eq!(*image.get_pixel(4, 5), white);
+        assert_eq!(*image.get_pixel(2, 4), Rgba([0, 0, 0, 255]));
+    }
+}
@@ -6,6 +6,10 @@ CREATE TABLE IF NOT EXISTS users (
     username VARCHAR(30) UNIQUE NOT NULL,
     email VARCHAR(255) UNIQUE NOT NULL,
     password_hash VARCHAR(255) NOT NULL,
+    display_name VARCHAR(50),
+    bio TEXT,
+    avatar_url VARCHAR(500),
+    roles TEXT[] NOT NULL DEFAULT '{}',
     created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
     updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
 );
@@ -19,28 +23,68 @@ CREATE TABLE IF NOT EXISTS memes (
     top_text TEXT,
     bottom_text TEXT,
     template_name VARCHAR(100),
-    views INTEGER DEFAULT 0,
-    likes INTEGER DEFAULT 0,
-    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+    captions JSONB,
+    overlays JSONB,
+    -- Source image and layout, kept so edits can re-render
+    source_url VARCHAR(500),
+    layout JSONB,
+    views INTEGER NOT NULL DEFAULT 0,
+    likes INTEGER NOT NULL DEFAULT 0,
+    -- Feed rankings, recomputed periodically by the server
+    hot_score DOUBLE PRECISION NOT NULL DEFAULT 0,
+    rising_score DOUBLE PRECISION NOT NULL DEFAULT 0,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    -- Set when the meme is moved to the trash
+    deleted_at TIMESTAMP WITH TIME ZONE,
+    -- Full-text index over title, captions and template, kept by a trigger
+    search_vector TSVECTOR
 );
 
 -- Indexes for better performance
-CREATE INDEX IF NOT EXISTS idx_memes_user_id ON memes(user_id);
-CREATE INDEX IF NOT EXISTS idx_memes_created_at ON memes(created_at DESC);
-CREATE INDEX IF NOT EXISTS idx_memes_likes ON memes(likes DESC);
+-- Feed indexes match the (key, id) tuples used by cursor pagination
+CREATE INDEX IF NOT EXISTS idx_memes_user_id ON memes(user_id, created_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_memes_created_at ON memes(created_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_memes_likes ON memes(likes DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_memes_hot_score ON memes(hot_score DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_memes_rising_score ON memes(rising_score DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_memes_deleted_at ON memes(deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_memes_search_vector ON memes USING GIN(search_vector);
 CREATE INDEX IF NOT EXISTS idx_users_username ON users(username);
 CREATE INDEX IF NOT EXISTS idx_users_email ON users(email);
 
--- Comments table (optional feature)
+-- Comments table; replies point at their parent comment
 CREATE TABLE IF NOT EXISTS comments (
     id UUID PRIMARY KEY,
     meme_id UUID NOT NULL REFERENCES memes(id) ON DELETE CASCADE,
     user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    parent_id UUID REFERENCES comments(id) ON DELETE CASCADE,
     content TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_comments_meme_id ON comments(meme_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_comments_parent_id ON comments(parent_id);
+
+-- Tags, stored normalized: lowercase letters, digits and underscores
+CREATE TABLE IF NOT EXISTS tags (
+    id UUID PRIMARY KEY,
+    name VARCHAR(30) UNIQUE NOT NULL,
     created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
 );
 
-CREATE INDEX IF NOT EXISTS idx_comments_meme_id ON comments(meme_id);
+-- text_pattern_ops lets tag autocomplete use the index for LIKE 'prefix%'
+CREATE INDEX IF NOT EXISTS idx_tags_name_prefix ON tags(name text_pattern_ops);
+
+CREATE TABLE IF NOT EXISTS meme_tags (
+    meme_id UUID NOT NULL REFERENCES memes(id) ON DELETE CASCADE,
+    tag_id UUID NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    -- Given in the request's tags rather than found as a #hashtag in the text
+    explicit BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (meme_id, tag_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_meme_tags_tag_id ON meme_tags(tag_id);
 
 -- Meme templates table
 CREATE TABLE IF NOT EXISTS meme_templates (
@@ -48,10 +92,50 @@ CREATE TABLE IF NOT EXISTS meme_templates (
     name VARCHAR(100) NOT NULL UNIQUE,
     image_url VARCHAR(500) NOT NULL,
     description TEXT,
+    layout JSONB,
     usage_count INTEGER DEFAULT 0,
     created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
 );
 
+CREATE INDEX IF NOT EXISTS idx_meme_templates_usage_count ON meme_templates(usage_count DESC);
+
+-- Source images uploaded for meme creation
+CREATE TABLE IF NOT EXISTS uploads (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    image_url VARCHAR(500) NOT NULL,
+    format VARCHAR(10) NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_uploads_user_id ON uploads(user_id);
+
+-- Login sessions; revoking one invalidates its access and refresh tokens
+CREATE TABLE IF NOT EXISTS sessions (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    revoked_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_sessions_user_id ON sessions(user_id);
+
+-- Rotating refresh tokens, stored as SHA-256 hashes. A token is single use;
+-- presenting a used one revokes its whole session.
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+    id UUID PRIMARY KEY,
+    session_id UUID NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+    token_hash VARCHAR(64) UNIQUE NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_session_id ON refresh_tokens(session_id);
+
 -- User likes tracking (to prevent duplicate likes)
 CREATE TABLE IF NOT EXISTS user_meme_likes (
     user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
@@ -60,6 +144,54 @@ CREATE TABLE IF NOT EXISTS user_meme_likes (
     PRIMARY KEY (user_id, meme_id)
 );
 
+CREATE INDEX IF NOT EXISTS idx_user_meme_likes_meme_id ON user_meme_likes(meme_id, created_at);
+
+-- Who follows whom
+CREATE TABLE IF NOT EXISTS follows (
+    follower_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    followee_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (follower_id, followee_id),
+    CHECK (follower_id <> followee_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_follows_follower_id ON follows(follower_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_follows_followee_id ON follows(followee_id, created_at DESC);
+
+-- Notifications; one unread row per recipient, kind and meme collects every
+-- actor until it is read
+CREATE TABLE IF NOT EXISTS notifications (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind VARCHAR(20) NOT NULL,
+    meme_id UUID REFERENCES memes(id) ON DELETE CASCADE,
+    -- The most recent actor
+    actor_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    actor_count INTEGER NOT NULL DEFAULT 0,
+    read_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_notifications_unread
+    ON notifications(user_id, kind, meme_id) NULLS NOT DISTINCT
+    WHERE read_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_notifications_user_id ON notifications(user_id, updated_at DESC, id DESC);
+
+CREATE TABLE IF NOT EXISTS notification_actors (
+    notification_id UUID NOT NULL REFERENCES notifications(id) ON DELETE CASCADE,
+    actor_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    PRIMARY KEY (notification_id, actor_id)
+);
+
+-- Opt-outs; users without a row get every notification
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    likes BOOLEAN NOT NULL DEFAULT TRUE,
+    comments BOOLEAN NOT NULL DEFAULT TRUE,
+    follows BOOLEAN NOT NULL DEFAULT TRUE
+);
+
 -- Function to update updated_at timestamp
 CREATE OR REPLACE FUNCTION update_updated_at_column()
 RETURNS TRIGGER AS $$
@@ -74,3 +206,38 @@ CREATE TRIGGER update_users_updated_at
     BEFORE UPDATE ON users
     FOR EACH ROW
     EXECUTE FUNCTION update_updated_at_column();
+
+-- Trigger for comments table
+CREATE TRIGGER update_comments_updated_at
+    BEFORE UPDATE ON comments
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- All of a meme's caption text: top, bottom, then any other text boxes
+CREATE OR REPLACE FUNCTION meme_caption_text(top_text TEXT, bottom_text TEXT, captions JSONB)
+RETURNS TEXT AS $$
+    SELECT concat_ws(' ', top_text, bottom_text, (
+        SELECT string_agg(value, ' ')
+        FROM jsonb_each_text(COALESCE(captions, '{}'::jsonb))
+        WHERE key NOT IN ('top', 'bottom')
+    ));
+$$ LANGUAGE sql IMMUTABLE;
+
+-- Rebuild a meme's search vector; titles rank above captions, captions
+-- above the template name
+CREATE OR REPLACE FUNCTION update_memes_search_vector()
+RETURNS TRIGGER AS $$
+BEGIN
+    NEW.search_vector :=
+        setweight(to_tsvector('english', COALESCE(NEW.title, '')), 'A') ||
+        setweight(to_tsvector('english', meme_caption_text(NEW.top_text, NEW.bottom_text, NEW.captions)), 'B') ||
+        setweight(to_tsvector('english', COALESCE(NEW.template_name, '')), 'C');
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Trigger for memes search
+CREATE TRIGGER update_memes_search_vector
+    BEFORE INSERT OR UPDATE OF title, top_text, bottom_text, captions, template_name ON memes
+    FOR EACH ROW
+    EXECUTE FUNCTION update_memes_search_vector();
@@ -0,0 +1,126 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::SearchResult;
+use crate::pagination::{decode_cursor, encode_cursor, finish_page, page_size};
+
+pub const MAX_SEARCH_RESULTS: i64 = 50;
+const MAX_QUERY_LENGTH: usize = 200;
+const MAX_QUERY_TERMS: usize = 8;
+
+/// Position after the last result of a search page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchCursor {
+    pub rank: f32,
+    pub id: Uuid,
+}
+
+/// A parsed `GET /api/search` request.
+#[derive(Debug, Clone)]
+pub struct SearchRequest {
+    /// Prefix `tsquery` built from `q`, see `prefix_tsquery`.
+    pub tsquery: String,
+    pub template: Option<String>,
+    pub author: Option<String>,
+    /// Inclusive lower and exclusive upper bounds on `created_at`.
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub after: Option<SearchCursor>,
+    pub limit: i64,
+}
+
+impl SearchRequest {
+    /// Reads `q`, `template`, `author`, `from`, `to`, `cursor` and `limit`.
+    /// Dates are RFC 3339 timestamps or `YYYY-MM-DD`; a plain date as `to`
+    /// includes that whole day.
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Self, AppError> {
+        let q = query.get("q").map(|q| q.trim()).unwrap_or_default();
+        if q.len() > MAX_QUERY_LENGTH {
+            return Err(AppError::BadRequest(format!(
+                "q must be at most {} characters",
+                MAX_QUERY_LENGTH
+            )));
+        }
+        let tsquery = prefix_tsquery(q)
+            .ok_or_else(|| AppError::BadRequest("q must contain at least one word".to_string()))?;
+
+        let filter = |key: &str| {
+            query
+                .get(key)
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+
+        Ok(SearchRequest {
+            tsquery,
+            template: filter("template"),
+            author: filter("author"),
+            from: query.get("from").map(|v| parse_date(v, false)).transpose()?,
+            to: query.get("to").map(|v| parse_date(v, true)).transpose()?,
+            after: query.get("cursor").map(|c| decode_cursor(c)).transpose()?,
+            limit: page_size(query, MAX_SEARCH_RESULTS)?,
+        })
+    }
+
+    /// `finish_page` for search results, continuing after the last one's rank.
+    pub fn finish(&self, results: &mut Vec<SearchResult>) -> Option<String> {
+        finish_page(results, self.limit, |result| {
+            encode_cursor(&SearchCursor {
+                rank: result.rank,
+                id: result.meme.id,
+            })
+        })
+    }
+}
+
+/// Turns free text into a `tsquery` that requires every word, each matched
+/// as a prefix so partially typed words still find results. Only letters and
+/// digits are kept, so the output is always valid `to_tsquery` syntax.
+pub fn prefix_tsquery(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .take(MAX_QUERY_TERMS)
+        .map(|term| format!("{}:*", term.to_lowercase()))
+        .collect();
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" & "))
+    }
+}
+
+fn parse_date(value: &str, end_of_day: bool) -> Result<DateTime<Utc>, AppError> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| AppError::BadRequest(format!("Invalid date: {}", value)))?;
+    let start = date.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc();
+    Ok(if end_of_day { start + Duration::days(1) } else { start })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_tsquery_strips_operators() {
+        assert_eq!(prefix_tsquery("Distracted boy").as_deref(), Some("distracted:* & boy:*"));
+        assert_eq!(prefix_tsquery("cat') | !dog:*").as_deref(), Some("cat:* & dog:*"));
+        assert_eq!(prefix_tsquery(" &|! "), None);
+    }
+
+    #[test]
+    fn test_date_range_includes_whole_day() {
+        let from = parse_date("2024-03-01", false).unwrap();
+        let to = parse_date("2024-03-01", true).unwrap();
+        assert_eq!(to - from, Duration::days(1));
+        assert!(parse_date("March 1st", false).is_err());
+    }
+}
@@ -0,0 +1,111 @@
+pub const MAX_TAGS: usize = 10;
+pub const MAX_TAG_LENGTH: usize = 30;
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Lowercases a tag and strips one leading `#`. Returns `None` unless what
+/// remains is 1 to `MAX_TAG_LENGTH` letters, digits or underscores.
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim();
+    let tag = tag.strip_prefix('#').unwrap_or(tag).to_lowercase();
+
+    let length = tag.chars().count();
+    if length == 0 || length > MAX_TAG_LENGTH || !tag.chars().all(is_tag_char) {
+        return None;
+    }
+    Some(tag)
+}
+
+/// Finds `#hashtags` in free text. A `#` only starts a tag at the beginning
+/// of a word, so `C#` and `issue#12` are ignored.
+pub fn extract_hashtags(text: &str) -> Vec<String> {
+    let mut tags = Vec::new();
+    let mut previous = None;
+
+    for (i, c) in text.char_indices() {
+        if c == '#' && !previous.is_some_and(is_tag_char) {
+            let rest = &text[i + 1..];
+            let end = rest.find(|c: char| !is_tag_char(c)).unwrap_or(rest.len());
+            if let Some(tag) = normalize_tag(&rest[..end]) {
+                tags.push(tag);
+            }
+        }
+        previous = Some(c);
+    }
+
+    tags
+}
+
+/// A meme's tags, split by where they came from. Hashtags are re-read from
+/// the text on every edit, while explicit tags stay until replaced.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MemeTags {
+    pub explicit: Vec<String>,
+    pub extracted: Vec<String>,
+}
+
+impl MemeTags {
+    pub fn is_empty(&self) -> bool {
+        self.explicit.is_empty() && self.extracted.is_empty()
+    }
+}
+
+/// Explicit tags and the hashtags found in `texts`, normalized, without
+/// duplicates and capped at `MAX_TAGS` together, explicit ones first. Invalid
+/// explicit tags are dropped; requests are validated before this point.
+pub fn collect_tags<'a>(explicit: &[String], texts: impl IntoIterator<Item = &'a str>) -> MemeTags {
+    let mut tags = MemeTags::default();
+    let explicit = explicit.iter().filter_map(|tag| normalize_tag(tag)).map(|tag| (tag, true));
+    let found = texts.into_iter().flat_map(extract_hashtags).map(|tag| (tag, false));
+
+    for (tag, is_explicit) in explicit.chain(found) {
+        if tags.explicit.len() + tags.extracted.len() == MAX_TAGS {
+            break;
+        }
+        if tags.explicit.contains(&tag) || tags.extracted.contains(&tag) {
+            continue;
+        }
+        if is_explicit {
+            tags.explicit.push(tag);
+        } else {
+            tags.extracted.push(tag);
+        }
+    }
+
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_tag() {
+        assert_eq!(normalize_tag(" #Monday_Mood ").as_deref(), Some("monday_mood"));
+        assert_eq!(normalize_tag("#"), None);
+        assert_eq!(normalize_tag("two words"), None);
+        assert_eq!(normalize_tag(&"a".repeat(MAX_TAG_LENGTH + 1)), None);
+    }
+
+    #[test]
+    fn test_collect_tags_merges_hashtags() {
+        let tags = collect_tags(
+            &["Cats".to_string()],
+            ["When it's #Monday again #cats", "learning C# issue#12 (#dev)"],
+        );
+        assert_eq!(tags.explicit, vec!["cats"]);
+        assert_eq!(tags.extracted, vec!["monday", "dev"]);
+    }
+
+    #[test]
+    fn test_hashtag_edited_out_of_title_is_dropped() {
+        let created = collect_tags(&["cats".to_string()], ["Caturday #foo"]);
+        assert_eq!(created.extracted, vec!["foo"]);
+
+        // An edit without `tags` keeps only the explicit ones and re-reads the text
+        let edited = collect_tags(&created.explicit, ["Caturday"]);
+        assert_eq!(edited, MemeTags { explicit: vec!["cats".to_string()], extracted: Vec::new() });
+    }
+}
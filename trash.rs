@@ -0,0 +1,62 @@
+use std::time::Duration as StdDuration;
+
+use chrono::Duration;
+use sqlx::PgPool;
+
+use crate::config;
+use crate::database;
+use crate::uploads;
+
+/// How long deleted memes stay restorable, and how often expired ones are
+/// purged.
+#[derive(Debug, Clone)]
+pub struct TrashConfig {
+    pub retention: Duration,
+    pub purge_interval: StdDuration,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig {
+            retention: Duration::days(30),
+            purge_interval: StdDuration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl TrashConfig {
+    /// Reads `MEME_TRASH_RETENTION_DAYS` and `MEME_TRASH_PURGE_INTERVAL_SECONDS`.
+    pub fn from_env() -> Self {
+        let defaults = TrashConfig::default();
+        TrashConfig {
+            retention: config::read_days("MEME_TRASH_RETENTION_DAYS").unwrap_or(defaults.retention),
+            purge_interval: config::read_seconds("MEME_TRASH_PURGE_INTERVAL_SECONDS")
+                .unwrap_or(defaults.purge_interval),
+        }
+    }
+}
+
+/// Hard-deletes memes whose restore window has passed, along with their
+/// image files. Returns how many were purged.
+pub async fn purge_expired(pool: &PgPool, config: &TrashConfig) -> Result<usize, sqlx::Error> {
+    let memes = database::purge_deleted_memes(pool, config.retention).await?;
+    for meme in &memes {
+        uploads::remove_meme_files(meme.id, &meme.image_url, meme.source_url.as_deref());
+    }
+
+    Ok(memes.len())
+}
+
+/// Runs `purge_expired` on the configured interval for the life of the server.
+pub fn spawn_purge_task(pool: PgPool, trash: TrashConfig) {
+    config::spawn_periodic(trash.purge_interval, move || {
+        let (pool, trash) = (pool.clone(), trash.clone());
+        async move {
+            match purge_expired(&pool, &trash).await {
+                Ok(0) => {}
+                Ok(count) => log::info!("Purged {} memes from the trash", count),
+                Err(e) => log::error!("Failed to purge trashed memes: {}", e),
+            }
+        }
+    });
+}
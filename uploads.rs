@@ -0,0 +1,266 @@
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path};
+
+use actix_multipart::Multipart;
+use actix_web::web;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures_util::TryStreamExt;
+use image::ImageFormat;
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::image_limits::{ImageLimits, ImageRejection};
+
+pub const DEFAULT_TEMPLATE_PATH: &str = "uploads/templates/default.jpg";
+pub const TEMPLATES_DIR: &str = "uploads/templates";
+pub const ORIGINALS_DIR: &str = "uploads/originals";
+pub const MEMES_DIR: &str = "uploads/memes";
+const TMP_DIR: &str = "uploads/tmp";
+
+const MAX_IMAGE_BYTES: usize = 10 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum UploadError {
+    TooLarge(String),
+    Unsupported(String),
+    Invalid(String),
+    Malformed(String),
+    Io(String),
+}
+
+impl From<ImageRejection> for UploadError {
+    fn from(rejection: ImageRejection) -> Self {
+        match rejection {
+            ImageRejection::Unsupported(msg) => UploadError::Unsupported(msg),
+            ImageRejection::TooLarge(msg) => UploadError::TooLarge(msg),
+            ImageRejection::Malformed(msg) => UploadError::Malformed(msg),
+        }
+    }
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadError::TooLarge(msg)
+            | UploadError::Unsupported(msg)
+            | UploadError::Invalid(msg)
+            | UploadError::Malformed(msg)
+            | UploadError::Io(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+/// An accepted source image on disk.
+#[derive(Debug)]
+pub struct StoredImage {
+    pub path: String,
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+    pub size_bytes: u64,
+}
+
+/// Returns the file extension used when storing an accepted source format.
+pub fn extension_for(format: ImageFormat) -> Option<&'static str> {
+    match format {
+        ImageFormat::Png => Some("png"),
+        ImageFormat::Jpeg => Some("jpg"),
+        ImageFormat::Gif => Some("gif"),
+        ImageFormat::WebP => Some("webp"),
+        _ => None,
+    }
+}
+
+/// Decodes a base64 image (optionally wrapped in a `data:` URL) and checks its
+/// header against `limits`.
+pub fn decode_base64_image(data: &str, limits: &ImageLimits) -> Result<(Vec<u8>, ImageFormat), UploadError> {
+    let payload = match data.split_once(";base64,") {
+        Some((prefix, rest)) if prefix.starts_with("data:") => rest,
+        _ => data,
+    };
+
+    // Reject oversized payloads before allocating the decoded buffer
+    if payload.len() / 4 * 3 > MAX_IMAGE_BYTES {
+        return Err(UploadError::TooLarge("Image exceeds the 10 MB limit".to_string()));
+    }
+
+    let bytes = STANDARD
+        .decode(payload.trim())
+        .map_err(|_| UploadError::Invalid("image_data is not valid base64".to_string()))?;
+
+    let info = limits.inspect(&bytes)?;
+
+    Ok((bytes, info.format))
+}
+
+/// Stores an uploaded source image so the meme can be re-rendered later.
+pub fn save_original(meme_id: Uuid, bytes: &[u8], format: ImageFormat) -> Result<String, UploadError> {
+    let extension = extension_for(format)
+        .ok_or_else(|| UploadError::Unsupported("Unsupported image format".to_string()))?;
+    fs::create_dir_all(ORIGINALS_DIR).map_err(|e| UploadError::Io(e.to_string()))?;
+
+    let path = format!("{}/{}.{}", ORIGINALS_DIR, meme_id, extension);
+    fs::write(&path, bytes).map_err(|e| UploadError::Io(format!("Failed to store image: {}", e)))?;
+
+    Ok(path)
+}
+
+/// Streams the `image` field of a multipart body to disk, enforcing the size
+/// cap while bytes arrive, then validates the file before moving it into
+/// `uploads/originals`.
+pub async fn save_multipart_image(
+    mut payload: Multipart,
+    upload_id: Uuid,
+    limits: ImageLimits,
+) -> Result<StoredImage, UploadError> {
+    let io_error = |e: std::io::Error| UploadError::Io(e.to_string());
+    let multipart_error = |e: actix_multipart::MultipartError| UploadError::Invalid(e.to_string());
+
+    tokio::fs::create_dir_all(TMP_DIR).await.map_err(io_error)?;
+    let tmp_path = format!("{}/{}.part", TMP_DIR, upload_id);
+
+    while let Some(mut field) = payload.try_next().await.map_err(multipart_error)? {
+        if field.name() != Some("image") {
+            continue;
+        }
+
+        let mut file = tokio::fs::File::create(&tmp_path).await.map_err(io_error)?;
+        let mut size: usize = 0;
+        let written: Result<(), UploadError> = async {
+            while let Some(chunk) = field.try_next().await.map_err(multipart_error)? {
+                size += chunk.len();
+                if size > MAX_IMAGE_BYTES {
+                    return Err(UploadError::TooLarge("Image exceeds the 10 MB limit".to_string()));
+                }
+                file.write_all(&chunk).await.map_err(io_error)?;
+            }
+            file.flush().await.map_err(io_error)
+        }
+        .await;
+        drop(file);
+
+        let stored = match written {
+            Ok(()) => {
+                let path = tmp_path.clone();
+                let limits = limits.clone();
+                web::block(move || inspect_and_store(&path, upload_id, size as u64, &limits))
+                    .await
+                    .map_err(|e| UploadError::Io(e.to_string()))
+                    .and_then(|r| r)
+            }
+            Err(e) => Err(e),
+        };
+
+        if stored.is_err() {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+        }
+        return stored;
+    }
+
+    Err(UploadError::Invalid("Missing 'image' file field".to_string()))
+}
+
+fn inspect_and_store(
+    tmp_path: &str,
+    upload_id: Uuid,
+    size_bytes: u64,
+    limits: &ImageLimits,
+) -> Result<StoredImage, UploadError> {
+    let io_error = |e: std::io::Error| UploadError::Io(e.to_string());
+
+    // The file is already capped at MAX_IMAGE_BYTES, so the header walk can
+    // work on the whole buffer
+    let bytes = fs::read(tmp_path).map_err(io_error)?;
+    let info = limits.inspect(&bytes)?;
+
+    fs::create_dir_all(ORIGINALS_DIR).map_err(io_error)?;
+    let extension = extension_for(info.format).unwrap_or("img");
+    let path = format!("{}/{}.{}", ORIGINALS_DIR, upload_id, extension);
+    fs::rename(tmp_path, &path).map_err(io_error)?;
+
+    Ok(StoredImage {
+        path,
+        format: info.format,
+        width: info.width,
+        height: info.height,
+        size_bytes,
+    })
+}
+
+/// Maps a stored `image_url` such as `/uploads/templates/drake.jpg` to its
+/// path on disk, refusing anything outside `dir`.
+pub fn stored_path(image_url: &str, dir: &str) -> Option<String> {
+    let relative = image_url.trim_start_matches('/');
+    let path = Path::new(relative);
+
+    let escapes = path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)));
+    if escapes || !path.starts_with(dir) {
+        return None;
+    }
+
+    Some(relative.to_string())
+}
+
+/// Removes a meme's rendered image, and its source image when that was sent
+/// inline and so belongs to the meme alone.
+pub fn remove_meme_files(meme_id: Uuid, image_url: &str, source_url: Option<&str>) {
+    remove_meme_image(image_url);
+
+    // Images sent inline as image_data are stored per meme; uploads are kept
+    if let Some(path) = source_url.and_then(|url| stored_path(url, ORIGINALS_DIR)) {
+        if path.starts_with(&format!("{}/{}.", ORIGINALS_DIR, meme_id)) {
+            remove_logged(&path);
+        }
+    }
+}
+
+/// Removes a rendered meme image.
+pub fn remove_meme_image(image_url: &str) {
+    if let Some(path) = stored_path(image_url, MEMES_DIR) {
+        remove_logged(&path);
+    }
+}
+
+/// Removes a template's base image.
+pub fn remove_template_image(image_url: &str) {
+    if let Some(path) = stored_path(image_url, TEMPLATES_DIR) {
+        remove_logged(&path);
+    }
+}
+
+/// Deletes `path`, logging failures other than the file already being gone.
+fn remove_logged(path: &str) {
+    if let Err(e) = fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            log::warn!("Failed to remove {}: {}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stored_path_rejects_traversal() {
+        assert_eq!(
+            stored_path("/uploads/templates/drake.jpg", TEMPLATES_DIR).as_deref(),
+            Some("uploads/templates/drake.jpg")
+        );
+        assert!(stored_path("/uploads/templates/../../etc/passwd", TEMPLATES_DIR).is_none());
+        assert!(stored_path("/uploads/memes/abc.jpg", TEMPLATES_DIR).is_none());
+    }
+
+    #[test]
+    fn test_decode_base64_image_rejects_garbage() {
+        let limits = ImageLimits::default();
+        assert!(decode_base64_image("not base64!", &limits).is_err());
+        assert!(matches!(
+            decode_base64_image(&STANDARD.encode(b"plain text"), &limits),
+            Err(UploadError::Unsupported(_))
+        ));
+    }
+}